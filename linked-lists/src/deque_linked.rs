@@ -1,8 +1,8 @@
-/// Doubly-linked list.
-/// Each node should have exactly two pointers to it, by the list or other nodes.
-/// the first: list's head and next node;
-/// the middle: the prev and next node;
-/// the last: the prev node and the list's tail.
+//! Doubly-linked list.
+//! Each node should have exactly two pointers to it, by the list or other nodes.
+//! the first: list's head and next node;
+//! the middle: the prev and next node;
+//! the last: the prev node and the list's tail.
 
 use std::rc::Rc;
 use std::cell::{Ref, RefMut, RefCell};
@@ -24,7 +24,7 @@ struct Node<T> {
 impl<T> Node<T> {
     fn new(elem: T) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Node {
-            elem: elem,
+            elem,
             prev: None,
             next: None,
         }))
@@ -104,33 +104,35 @@ impl<T> List<T> {
         })
     }
 
-    pub fn peek_front(&self) -> Option<Ref<T>> {
+    pub fn peek_front(&self) -> Option<Ref<'_, T>> {
         self.head.as_ref().map(|node| {
             // the lifetime of the Ref which borrow() returned
             Ref::map(node.borrow(), |node| &node.elem)
         })
     }
 
-    pub fn peek_back(&self) -> Option<Ref<T>> {
+    pub fn peek_back(&self) -> Option<Ref<'_, T>> {
         self.tail.as_ref().map(|node| {
             Ref::map(node.borrow(), |node| &node.elem)
         })
     }
     
-    pub fn peek_back_mut(&mut self) -> Option<RefMut<T>> {
+    pub fn peek_back_mut(&mut self) -> Option<RefMut<'_, T>> {
         self.tail.as_ref().map(|node| {
             RefMut::map(node.borrow_mut(), |node| &mut node.elem)
         })
     }
     
-    pub fn peek_front_mut(&mut self) -> Option<RefMut<T>> {
+    pub fn peek_front_mut(&mut self) -> Option<RefMut<'_, T>> {
         self.head.as_ref().map(|node| {
             RefMut::map(node.borrow_mut(), |node| &mut node.elem)
         })
     }
+}
 
-    pub fn into_iter(self) -> IntoIter<T> {
-        IntoIter(self)
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -142,6 +144,14 @@ impl<T> Drop for List<T> {
 
 pub struct IntoIter<T>(List<T>);
 
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
 impl<T> Iterator for IntoIter<T> {
     type Item = T;
     fn next(&mut self) -> Option<T> {
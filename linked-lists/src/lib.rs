@@ -6,6 +6,7 @@ mod unsafe_linked;
 mod vec_list;
 
 pub use persis_linked::List;
+pub use deque_linked::List as Deque;
 
 #[cfg(test)]
 mod tests {
@@ -20,7 +20,7 @@ impl<T> List<T> {
     fn append(&self, elem: T) -> List<T> {
         List {
             head: Some(Rc::new(Node {
-                elem: elem,
+                elem,
                 // Option<T> impl the Clone trait, evaluating <T>.clone() when it's Some<T>
                 // which is Rc<Node<T>> here.
                 next: self.head.clone(),
@@ -38,7 +38,7 @@ impl<T> List<T> {
         self.head.as_ref().map( |node| &node.elem )
     }
 
-    fn iter(&self) -> Iter<T> {
+    fn iter(&self) -> Iter<'_, T> {
         Iter { next: self.head.as_deref() }
     }
 }
@@ -1,4 +1,3 @@
-use std::mem;
 
 
 /// One-direction linked stack with a head attribute pointing to it's latest aka top node.
@@ -41,7 +40,7 @@ impl<T> List<T> {
     /// Change the list's head pointing to the new created node.
     pub fn push(&mut self, elem: T) {
         let new_node = Node {
-            elem: elem, 
+            elem, 
             // Takes the option value(Link<T>) out, leaving a None in its place.
             next: self.head.take(),
         };
@@ -60,13 +59,13 @@ impl<T> List<T> {
         IntoIter(self)
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         // head is an Option wrap the Box pointing to Node, as_deref() will deref the Option's content
         // that's deref the Box and return &Node.
         Iter { next: self.head.as_deref() }
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut { next: self.head.as_deref_mut() }
     }
 }
@@ -76,7 +75,7 @@ impl<T> Drop for List<T> {
         let mut cur_link = self.head.take();
 
         while let Some(mut boxed_node) = cur_link {
-            cur_link = mem::replace(&mut boxed_node.next, None);
+            cur_link = boxed_node.next.take();
         }
     }
 }
@@ -163,9 +162,9 @@ mod test {
         assert_eq!(list.peek(), Some(&3));
         assert_eq!(list.peek_mut(), Some(&mut 3));
 
-        list.peek_mut().map(|elem| {
+        if let Some(elem) = list.peek_mut() {
             *elem = 42
-        });
+        }
 
         assert_eq!(list.peek(), Some(&42));
         assert_eq!(list.pop(), Some(42));
@@ -20,7 +20,7 @@ impl<T> List<T> {
 
     pub fn push(&mut self, elem: T) {
         let mut new_tail = Box::new(Node {
-        elem: elem,
+        elem,
         next: None,
         });
 
@@ -24,6 +24,7 @@ impl<T> Node<T> {
     Node { next: None, prev: None, element }
   }
 
+  #[allow(clippy::boxed_local)]
   fn into_element(self: Box<Self>) -> T {
     self.element
   }
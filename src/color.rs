@@ -0,0 +1,235 @@
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+/// When to color the output, as picked by `--color`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// when writing to a terminal, and `NO_COLOR` isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("unknown color choice {:?}, expected auto, always or never", s)),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// Whether to color output that goes to a terminal or not.
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                let dumb = env::var_os("TERM").is_some_and(|term| term == "dumb");
+                terminal && !no_color && !dumb
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "black" => Color::Black,
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "white" => Color::White,
+            _ => return Err(format!("unknown color {:?}", s)),
+        })
+    }
+}
+
+/// How one kind of output looks.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    fn fg(color: Color) -> Self {
+        Style { fg: Some(color), ..Style::default() }
+    }
+
+    /// Wrap `text` in the escape codes for the style.
+    pub fn paint<T: fmt::Display>(&self, text: T) -> Painted<T> {
+        Painted { style: *self, text }
+    }
+}
+
+/// Text along with the style it's printed in.
+pub struct Painted<T> {
+    style: Style,
+    text: T,
+}
+
+impl<T: fmt::Display> fmt::Display for Painted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.style == Style::default() {
+            return write!(f, "{}", self.text);
+        }
+        let mut codes = Vec::new();
+        if self.style.bold {
+            codes.push("1".to_string());
+        }
+        if self.style.underline {
+            codes.push("4".to_string());
+        }
+        if let Some(fg) = self.style.fg {
+            codes.push((30 + fg as u8).to_string());
+        }
+        if let Some(bg) = self.style.bg {
+            codes.push((40 + bg as u8).to_string());
+        }
+        write!(f, "\x1b[{}m{}\x1b[0m", codes.join(";"), self.text)
+    }
+}
+
+/// The style of each kind of output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colors {
+    pub path: Style,
+    pub line: Style,
+    pub column: Style,
+    pub matched: Style,
+    pub separator: Style,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            path: Style::fg(Color::Magenta),
+            line: Style::fg(Color::Green),
+            column: Style::fg(Color::Green),
+            matched: Style { bold: true, ..Style::fg(Color::Red) },
+            separator: Style::fg(Color::Cyan),
+        }
+    }
+}
+
+impl Colors {
+    /// Change one attribute of the style of one kind of output.
+    pub fn apply(&mut self, spec: &ColorSpec) {
+        let style = match spec.kind.as_str() {
+            "path" => &mut self.path,
+            "line" => &mut self.line,
+            "column" => &mut self.column,
+            "match" => &mut self.matched,
+            _ => &mut self.separator,
+        };
+        match spec.change {
+            Change::None => *style = Style::default(),
+            Change::Fg(color) => style.fg = Some(color),
+            Change::Bg(color) => style.bg = Some(color),
+            Change::Bold(yes) => style.bold = yes,
+            Change::Underline(yes) => style.underline = yes,
+        }
+    }
+}
+
+/// A tweak to the default colors, given to `--colors`:
+/// `{kind}:{fg|bg}:{color}`, `{kind}:style:{bold|nobold|underline|nounderline}`,
+/// or `{kind}:none` to clear it, where the kind is path, line, column, match or separator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorSpec {
+    kind: String,
+    change: Change,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    None,
+    Fg(Color),
+    Bg(Color),
+    Bold(bool),
+    Underline(bool),
+}
+
+impl FromStr for ColorSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let kind = parts[0];
+        if !["path", "line", "column", "match", "separator"].contains(&kind) {
+            return Err(format!("unknown kind {:?} in color spec {:?}", kind, s));
+        }
+        let change = match parts[1..] {
+            ["none"] => Change::None,
+            ["fg", color] => Change::Fg(color.parse()?),
+            ["bg", color] => Change::Bg(color.parse()?),
+            ["style", "bold"] => Change::Bold(true),
+            ["style", "nobold"] => Change::Bold(false),
+            ["style", "underline"] => Change::Underline(true),
+            ["style", "nounderline"] => Change::Underline(false),
+            _ => return Err(format!("invalid color spec {:?}, expected something like match:fg:red", s)),
+        };
+        Ok(ColorSpec { kind: kind.to_string(), change })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Color, ColorChoice, ColorSpec, Colors, Style};
+
+    #[test]
+    fn specs() {
+        let mut colors = Colors::default();
+        for spec in &["match:fg:blue", "match:style:nobold", "path:none", "line:bg:yellow"] {
+            colors.apply(&spec.parse().unwrap());
+        }
+        assert_eq!(colors.matched, Style::fg(Color::Blue));
+        assert_eq!(colors.path, Style::default());
+        assert_eq!(colors.line.bg, Some(Color::Yellow));
+        assert!("match:fg:mauve".parse::<ColorSpec>().is_err());
+        assert!("title:fg:red".parse::<ColorSpec>().is_err());
+        assert!("match".parse::<ColorSpec>().is_err());
+    }
+
+    #[test]
+    fn paint() {
+        let colors = Colors::default();
+        assert_eq!(colors.matched.paint("foo").to_string(), "\x1b[1;31mfoo\x1b[0m");
+        assert_eq!(Style::default().paint("foo").to_string(), "foo");
+    }
+
+    #[test]
+    fn choice() {
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+        // piped output stays plain
+        assert!(!ColorChoice::Auto.enabled(false));
+    }
+}
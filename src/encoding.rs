@@ -0,0 +1,192 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::str::FromStr;
+
+/// How the bytes of an input are turned into text before searching.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// UTF-8, or UTF-16 if the input starts with a UTF-16 byte order mark
+    Auto,
+    /// UTF-8, or anything close to it: bytes are searched as they are
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Encoding::Auto),
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            _ => Err(format!("unknown encoding {:?}, expected auto, utf-8, utf-16le or utf-16be", s)),
+        }
+    }
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// Whether `head`, the start of a file, has a UTF-16 byte order mark.
+pub fn is_utf16(head: &[u8]) -> bool {
+    head.starts_with(UTF16LE_BOM) || head.starts_with(UTF16BE_BOM)
+}
+
+/// Wrap a reader so it hands out UTF-8 (or raw bytes, for `Utf8`).
+/// A byte order mark matching the encoding is dropped; with `Auto`
+/// the byte order mark, if any, decides the encoding.
+/// Also says how far into the input what it hands out starts: past a UTF-8
+/// byte order mark, or at 0 for UTF-16, whose offsets are into the UTF-8.
+pub fn decode<'a, R: Read + 'a>(encoding: Encoding, inner: R) -> io::Result<(Box<dyn BufRead + 'a>, u64)> {
+    let mut reader = BufReader::new(inner);
+    let head = reader.fill_buf()?;
+    let (encoding, bom) = match encoding {
+        Encoding::Auto if head.starts_with(UTF16LE_BOM) => (Encoding::Utf16Le, UTF16LE_BOM.len()),
+        Encoding::Auto if head.starts_with(UTF16BE_BOM) => (Encoding::Utf16Be, UTF16BE_BOM.len()),
+        Encoding::Auto | Encoding::Utf8 if head.starts_with(UTF8_BOM) => (Encoding::Utf8, UTF8_BOM.len()),
+        Encoding::Utf16Le if head.starts_with(UTF16LE_BOM) => (encoding, UTF16LE_BOM.len()),
+        Encoding::Utf16Be if head.starts_with(UTF16BE_BOM) => (encoding, UTF16BE_BOM.len()),
+        _ => (encoding, 0),
+    };
+    reader.consume(bom);
+
+    Ok(match encoding {
+        Encoding::Auto | Encoding::Utf8 => (Box::new(reader), bom as u64),
+        Encoding::Utf16Le => (Box::new(BufReader::new(Utf16Decoder::new(reader, false))), 0),
+        Encoding::Utf16Be => (Box::new(BufReader::new(Utf16Decoder::new(reader, true))), 0),
+    })
+}
+
+/// Transcodes UTF-16 to UTF-8 on the fly.
+/// Unpaired surrogates and a dangling odd byte become U+FFFD.
+struct Utf16Decoder<R> {
+    inner: R,
+    big_endian: bool,
+    /// a byte left over from a read of odd length
+    odd: Option<u8>,
+    /// a high surrogate waiting for its other half
+    high: Option<u16>,
+    /// decoded text not handed out yet
+    decoded: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> Utf16Decoder<R> {
+    fn new(inner: R, big_endian: bool) -> Self {
+        Utf16Decoder { inner, big_endian, odd: None, high: None, decoded: Vec::new(), pos: 0, eof: false }
+    }
+
+    fn push(&mut self, c: char) {
+        let mut utf8 = [0; 4];
+        self.decoded.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        self.decoded.clear();
+        self.pos = 0;
+
+        let mut raw = [0; 8192];
+        let mut len = 0;
+        if let Some(byte) = self.odd.take() {
+            raw[0] = byte;
+            len = 1;
+        }
+        let read = loop {
+            match self.inner.read(&mut raw[len..]) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result?,
+            }
+        };
+        if read == 0 {
+            self.eof = true;
+            if len == 1 || self.high.take().is_some() {
+                self.push(char::REPLACEMENT_CHARACTER);
+            }
+            return Ok(());
+        }
+        len += read;
+        if len % 2 == 1 {
+            self.odd = Some(raw[len - 1]);
+        }
+
+        for pair in raw[..len - len % 2].chunks(2) {
+            let pair = [pair[0], pair[1]];
+            let unit = if self.big_endian { u16::from_be_bytes(pair) } else { u16::from_le_bytes(pair) };
+            if let Some(high) = self.high.take() {
+                if (0xDC00..0xE000).contains(&unit) {
+                    let c = 0x10000 + ((u32::from(high) - 0xD800) << 10) + (u32::from(unit) - 0xDC00);
+                    self.push(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    continue;
+                }
+                self.push(char::REPLACEMENT_CHARACTER);
+            }
+            match unit {
+                0xD800..=0xDBFF => self.high = Some(unit),
+                0xDC00..=0xDFFF => self.push(char::REPLACEMENT_CHARACTER),
+                _ => self.push(char::from_u32(u32::from(unit)).unwrap_or(char::REPLACEMENT_CHARACTER)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Utf16Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let len = buf.len().min(self.decoded.len() - self.pos);
+        buf[..len].copy_from_slice(&self.decoded[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, Encoding};
+    use std::io::Read;
+
+    fn decoded(encoding: Encoding, input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        decode(encoding, input).unwrap().0.read_to_end(&mut out).unwrap();
+        out
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = if big_endian { vec![0xFE, 0xFF] } else { vec![0xFF, 0xFE] };
+        for unit in text.encode_utf16() {
+            if big_endian {
+                bytes.extend_from_slice(&unit.to_be_bytes());
+            } else {
+                bytes.extend_from_slice(&unit.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn sniff_bom() {
+        let text = "foo: 10\nbär: 20 🦀\n";
+        assert_eq!(decoded(Encoding::Auto, &utf16(text, false)), text.as_bytes());
+        assert_eq!(decoded(Encoding::Auto, &utf16(text, true)), text.as_bytes());
+        assert_eq!(decoded(Encoding::Auto, b"\xEF\xBB\xBFfoo"), b"foo");
+        // no byte order mark, no transcoding, and stray bytes are kept
+        assert_eq!(decoded(Encoding::Auto, b"caf\xE9"), b"caf\xE9");
+    }
+
+    #[test]
+    fn forced() {
+        // utf-8 never looks for a UTF-16 byte order mark
+        assert_eq!(decoded(Encoding::Utf8, b"\xFF\xFEa\x00"), b"\xFF\xFEa\x00");
+        assert_eq!(decoded(Encoding::Utf16Le, b"a\x00b\x00"), b"ab");
+        assert_eq!(decoded(Encoding::Utf16Be, b"\x00a\xD8\x00"), "a\u{FFFD}".as_bytes());
+    }
+}
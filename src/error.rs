@@ -0,0 +1,109 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong in a search.
+#[derive(Debug)]
+pub enum Error {
+    /// an input couldn't be opened or read
+    Io { path: PathBuf, source: io::Error },
+    /// the pattern didn't compile
+    Pattern { pattern: String, source: regex::Error },
+    /// a `--glob` didn't compile
+    Glob { glob: String, source: ignore::Error },
+    /// a file type is unknown or badly defined
+    Type { source: ignore::Error },
+    /// a line of a pattern file isn't valid UTF-8
+    Utf8 { path: PathBuf, line: u64 },
+    /// results couldn't be written, to the given file or else to stdout
+    Output { path: Option<PathBuf>, source: io::Error },
+}
+
+impl Error {
+    /// Whether the error is one with an input, as opposed to the pattern
+    /// or the output. A search carries on past those, to the next input.
+    pub fn is_input(&self) -> bool {
+        matches!(self, Error::Io { .. })
+    }
+
+    /// Whether whoever reads our output went away, e.g. `grrs ... | head`.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, Error::Output { source, .. } if source.kind() == io::ErrorKind::BrokenPipe)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Pattern { pattern, source } => write!(f, "invalid pattern {:?}\n{}", pattern, source),
+            Error::Glob { glob, source } => write!(f, "invalid glob {:?}: {}", glob, source),
+            Error::Type { source } => write!(f, "{}", source),
+            Error::Utf8 { path, line } => write!(f, "{}:{}: pattern is not valid UTF-8", path.display(), line),
+            Error::Output { path: Some(path), source } => {
+                write!(f, "failed to write results to {}: {}", path.display(), source)
+            }
+            Error::Output { path: None, source } => write!(f, "failed to write results: {}", source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::Output { source, .. } => Some(source),
+            Error::Pattern { source, .. } => Some(source),
+            Error::Glob { source, .. } | Error::Type { source } => Some(source),
+            Error::Utf8 { .. } => None,
+        }
+    }
+}
+
+/// A bare I/O error is one writing results to stdout;
+/// errors with inputs are always built with the input's path.
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Output { path: None, source }
+    }
+}
+
+impl From<walkdir::Error> for Error {
+    fn from(err: walkdir::Error) -> Self {
+        let path = err.path().map(PathBuf::from).unwrap_or_default();
+        Error::Io { path, source: err.into() }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[test]
+    fn messages() {
+        let err = Error::Io {
+            path: PathBuf::from("missing.txt"),
+            source: io::Error::new(io::ErrorKind::NotFound, "not found"),
+        };
+        assert_eq!(err.to_string(), "missing.txt: not found");
+        assert!(err.is_input());
+
+        // inputs are searched as bytes, so a failed read is never about UTF-8
+        let err = Error::Io {
+            path: PathBuf::from("disk.img"),
+            source: io::Error::new(io::ErrorKind::InvalidData, "bad sector"),
+        };
+        assert_eq!(err.to_string(), "disk.img: bad sector");
+
+        let err = Error::Utf8 { path: PathBuf::from("patterns.txt"), line: 3 };
+        assert_eq!(err.to_string(), "patterns.txt:3: pattern is not valid UTF-8");
+
+        let err = Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"));
+        assert!(err.is_broken_pipe());
+        assert!(!err.is_input());
+    }
+}
@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::Matcher;
+
+/// A `--format` template, like `{path}:{line}: {1}={2}`, printed once per match.
+/// `{path}`, `{line}` and `{column}` are where the match is, `{N}` is what
+/// capture group N matched (0 for the whole match) and `{name}` what the
+/// group with that name matched. Groups that didn't take part are empty,
+/// and `{{` and `}}` stand for braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Format {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Path,
+    Line,
+    Column,
+    Group(usize),
+    Named(String),
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed || name.is_empty() || name.contains('{') {
+                        return Err(format!("invalid placeholder in format {:?}", s));
+                    }
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(match name.as_str() {
+                        "path" => Piece::Path,
+                        "line" => Piece::Line,
+                        "column" => Piece::Column,
+                        _ => match name.parse() {
+                            Ok(index) => Piece::Group(index),
+                            Err(_) => Piece::Named(name),
+                        },
+                    });
+                }
+                '}' => return Err(format!("unmatched `}}` in format {:?}, write `}}}}` for a brace", s)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Format { pieces })
+    }
+}
+
+impl Format {
+    /// Print one match, given the byte range of each of its capture groups
+    /// in the line, and a line break after it.
+    pub fn render(
+        &self,
+        out: &mut dyn Write,
+        path: &Path,
+        line_number: u64,
+        line: &[u8],
+        groups: &[Option<(usize, usize)>],
+        matcher: &Matcher,
+    ) -> io::Result<()> {
+        let group = |index: Option<usize>| {
+            let (start, end) = index.and_then(|index| groups.get(index).copied().flatten()).unwrap_or((0, 0));
+            String::from_utf8_lossy(&line[start..end])
+        };
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => write!(out, "{}", text)?,
+                Piece::Path => write!(out, "{}", path.display())?,
+                Piece::Line => write!(out, "{}", line_number)?,
+                Piece::Column => write!(out, "{}", groups[0].map_or(0, |(start, _)| start) + 1)?,
+                Piece::Group(index) => write!(out, "{}", group(Some(*index)))?,
+                Piece::Named(name) => write!(out, "{}", group(matcher.group_index(name)))?,
+            }
+        }
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Format, Piece};
+
+    #[test]
+    fn parse() {
+        let format: Format = "{path}:{{{line}}} {1}{name}".parse().unwrap();
+        assert_eq!(
+            format.pieces,
+            [
+                Piece::Path,
+                Piece::Text(":{".to_string()),
+                Piece::Line,
+                Piece::Text("} ".to_string()),
+                Piece::Group(1),
+                Piece::Named("name".to_string()),
+            ]
+        );
+        assert!("{path".parse::<Format>().is_err());
+        assert!("{}".parse::<Format>().is_err());
+        assert!("a}b".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Write `s` as a JSON string, quotes and all.
+pub fn write_str(out: &mut dyn Write, s: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    let mut last = 0;
+    for (i, c) in s.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            c if (c as u32) < 0x20 || c == '\u{7F}' => "",
+            _ => continue,
+        };
+        out.write_all(&s.as_bytes()[last..i])?;
+        if escaped.is_empty() {
+            write!(out, "\\u{:04x}", c as u32)?;
+        } else {
+            out.write_all(escaped.as_bytes())?;
+        }
+        last = i + c.len_utf8();
+    }
+    out.write_all(&s.as_bytes()[last..])?;
+    out.write_all(b"\"")
+}
+
+/// Write text that might not be UTF-8: `{"text":"..."}` if it is,
+/// or else `{"bytes":"..."}` with the bytes in base64.
+pub fn write_data(out: &mut dyn Write, data: &[u8]) -> io::Result<()> {
+    match std::str::from_utf8(data) {
+        Ok(text) => {
+            out.write_all(b"{\"text\":")?;
+            write_str(out, text)?;
+        }
+        Err(_) => write!(out, "{{\"bytes\":\"{}\"", base64(data))?,
+    }
+    out.write_all(b"}")
+}
+
+/// Paths go the same way as text, since on Unix they're bytes too.
+pub fn write_path(out: &mut dyn Write, path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        write_data(out, path.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        write_data(out, path.to_string_lossy().as_bytes())
+    }
+}
+
+/// Standard base64, with padding.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base64, write_data, write_str};
+
+    fn json(write: impl Fn(&mut Vec<u8>)) -> String {
+        let mut out = Vec::new();
+        write(&mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(json(|out| write_str(out, "a \"b\"\\c\n\t\u{1}\u{7F}é").unwrap()), r#""a \"b\"\\c\n\t\u0001\u007fé""#);
+    }
+
+    #[test]
+    fn data() {
+        assert_eq!(json(|out| write_data(out, b"caf\xC3\xA9").unwrap()), r#"{"text":"café"}"#);
+        assert_eq!(json(|out| write_data(out, b"caf\xE9").unwrap()), r#"{"bytes":"Y2Fm6Q=="}"#);
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+    }
+}
@@ -1,8 +1,32 @@
 use std::path::PathBuf;
 use structopt::StructOpt;
 
-pub use utils::type_of;
+pub use utils::{parse_size, type_of};
+pub use color::{ColorChoice, ColorSpec, Colors};
+pub use encoding::Encoding;
+pub use error::{Error, Result};
+pub use format::Format;
+pub use matcher::{Case, Matcher, MatcherBuilder};
+pub use output::{AtomicFile, Output};
+pub use parallel::Sort;
+pub use printer::Printer;
+pub use replace::Rewriter;
+pub use search::{Binary, MmapChoice, Mode, Searcher};
+pub use walk::{Walk, WalkBuilder};
 
+mod color;
+mod encoding;
+mod error;
+mod format;
+mod json;
+mod matcher;
+mod mmap;
+mod output;
+pub mod parallel;
+mod printer;
+mod replace;
+mod search;
+pub mod types;
 mod utils;
 mod walk;
 
@@ -11,14 +35,234 @@ mod walk;
 #[derive(Debug, StructOpt)]
 #[structopt(name="grrs example", about="An example for command line app.")]
 pub struct Cli {
-    /// the pattern to look for
+    /// a pattern to look for, as a regular expression;
+    /// lines that any of the patterns match are selected
+    #[structopt(short, long, number_of_values=1, required_unless_one=&["regexp", "file", "type-list"])]
+    pub pattern: Vec<String>,
+
+    /// a pattern to look for, same as --pattern
+    #[structopt(short="e", long, value_name="PATTERN", number_of_values=1)]
+    pub regexp: Vec<String>,
+
+    /// read patterns from FILE, one per line, or from stdin if it's `-`
+    #[structopt(short="f", long, value_name="FILE", parse(from_os_str), number_of_values=1)]
+    pub file: Vec<PathBuf>,
+
+    /// treat the patterns as literal strings instead of regular expressions
+    #[structopt(short="F", long)]
+    pub fixed_strings: bool,
+
+    /// show the line number of each match
+    #[structopt(short="n", long)]
+    pub line_number: bool,
+
+    /// show the byte offset of each matching line within its file
+    #[structopt(short, long)]
+    pub byte_offset: bool,
+
+    /// show the column of the first match in each line
+    #[structopt(long)]
+    pub column: bool,
+
+    /// print every match as `path:line:column:text`, for editors' quickfix lists
+    #[structopt(long)]
+    pub vimgrep: bool,
+
+    /// print results as JSON Lines: begin, match, context and end objects
+    /// for each file, and a summary at the end
+    #[structopt(long, conflicts_with_all=&["count", "files-with-matches", "files-without-match", "vimgrep"])]
+    pub json: bool,
+
+    /// show NUM lines after each match
+    #[structopt(short="A", long, value_name="NUM")]
+    pub after_context: Option<usize>,
+
+    /// show NUM lines before each match
+    #[structopt(short="B", long, value_name="NUM")]
+    pub before_context: Option<usize>,
+
+    /// show NUM lines before and after each match
+    #[structopt(short="C", long, value_name="NUM")]
+    pub context: Option<usize>,
+
+    /// only match whole words
     #[structopt(short, long)]
-    pub pattern: String,
+    pub word_regexp: bool,
+
+    /// only match whole lines
+    #[structopt(short="x", long)]
+    pub line_regexp: bool,
+
+    /// match regardless of case
+    #[structopt(short, long)]
+    pub ignore_case: bool,
+
+    /// match regardless of case, unless the pattern has an uppercase letter
+    #[structopt(short="S", long)]
+    pub smart_case: bool,
+
+    /// match across lines: the patterns run over whole files,
+    /// and every line a match touches is selected;
+    /// can't be used with --replace or --format, which work a line at a time
+    #[structopt(short="U", long, conflicts_with_all=&["replace", "format"])]
+    pub multiline: bool,
+
+    /// with --multiline, let `.` match line breaks too
+    #[structopt(long, requires="multiline")]
+    pub multiline_dotall: bool,
+
+    /// files bigger than SIZE, like 512K or 10M, are refused by --multiline,
+    /// since they have to be held in memory whole
+    #[structopt(long, value_name="SIZE", default_value="256M", parse(try_from_str=parse_size))]
+    pub multiline_max_size: u64,
+
+    /// select the lines that don't match
+    #[structopt(short="v", long)]
+    pub invert_match: bool,
+
+    /// print lines with every match replaced by TEMPLATE, where `$1` or `${name}`
+    /// stand for what a capture group matched
+    #[structopt(short, long, value_name="TEMPLATE")]
+    pub replace: Option<String>,
+
+    /// rewrite the files with the replacements instead of printing them;
+    /// every match in a UTF-8 file is replaced, so this can't be used with
+    /// --invert-match, --max-count or --encoding
+    #[structopt(long, requires="replace", conflicts_with_all=&["invert-match", "max-count", "encoding"])]
+    pub in_place: bool,
+
+    /// keep the original of each rewritten file as `name.bak`
+    #[structopt(long, requires="in-place")]
+    pub backup: bool,
+
+    /// print a unified diff of what --in-place would change, without changing it
+    #[structopt(long, requires="in-place")]
+    pub dry_run: bool,
+
+    /// print only the matched text of each match, on a line of its own;
+    /// long only, since `-o` is short for --output
+    #[structopt(long)]
+    pub only_matching: bool,
+
+    /// print each match through TEMPLATE, like `{path}:{line}: {1}={2}`, where
+    /// {path}, {line} and {column} are where it is and {N} or {name} a capture group
+    #[structopt(long, value_name="TEMPLATE", conflicts_with_all=&["json", "replace"])]
+    pub format: Option<Format>,
 
-    /// the path to the file to read
+    /// stop searching a file after NUM matching lines
+    #[structopt(short, long, value_name="NUM")]
+    pub max_count: Option<u64>,
+
+    /// leave out lines longer than NUM bytes, saying how many matches they had;
+    /// 0 means no limit
+    #[structopt(long, value_name="NUM")]
+    pub max_columns: Option<usize>,
+
+    /// print how many lines matched in each file instead of the lines
+    #[structopt(short, long)]
+    pub count: bool,
+
+    /// print only the paths of files with a match
+    #[structopt(short="l", long)]
+    pub files_with_matches: bool,
+
+    /// print only the paths of files without a match
+    #[structopt(short="L", long)]
+    pub files_without_match: bool,
+
+    /// the encoding of the inputs: auto (UTF-8, or UTF-16 when there's a byte order mark),
+    /// utf-8, utf-16le or utf-16be
+    #[structopt(short="E", long, default_value="auto")]
+    pub encoding: Encoding,
+
+    /// what to do with binary files: matches (just say whether they match),
+    /// skip, or text (search them like any other file)
+    #[structopt(long, default_value="matches")]
+    pub binary: Binary,
+
+    /// search binary files as if they were text, same as `--binary=text`
+    #[structopt(short="a", long)]
+    pub text: bool,
+
+    /// only search paths matching the glob, or not matching it with a leading `!`;
+    /// can be given more than once
+    #[structopt(short, long, number_of_values=1)]
+    pub glob: Vec<String>,
+
+    /// only search files of this type, like rust or md; can be given more than once
+    #[structopt(short="t", long="type", number_of_values=1)]
+    pub file_type: Vec<String>,
+
+    /// don't search files of this type; can be given more than once
+    #[structopt(short="T", long, number_of_values=1)]
+    pub type_not: Vec<String>,
+
+    /// define a file type as `name:glob`, or add a glob to an existing one
+    #[structopt(long, number_of_values=1)]
+    pub type_add: Vec<String>,
+
+    /// print the known file types and their globs, then exit
+    #[structopt(long)]
+    pub type_list: bool,
+
+    /// descend at most NUM directories below each path given
+    #[structopt(long, value_name="NUM")]
+    pub max_depth: Option<usize>,
+
+    /// skip files bigger than SIZE, like 512K or 10M, while walking directories
+    #[structopt(long, value_name="SIZE", parse(try_from_str=parse_size))]
+    pub max_filesize: Option<u64>,
+
+    /// search hidden files and directories too
+    #[structopt(long)]
+    pub hidden: bool,
+
+    /// don't leave out what `.gitignore`, `.ignore` and `.git/info/exclude` rule out
+    #[structopt(long)]
+    pub no_ignore: bool,
+
+    /// how many files to search at once; defaults to the number of CPUs
+    #[structopt(long)]
+    pub threads: Option<usize>,
+
+    /// the order to print results in: path (the same every run),
+    /// or none (as soon as each file is done)
+    #[structopt(long, default_value="path")]
+    pub sort: Sort,
+
+    /// memory map files instead of reading them (the default for big files)
+    #[structopt(long, overrides_with="no-mmap")]
+    pub mmap: bool,
+
+    /// never memory map files
+    #[structopt(long, overrides_with="mmap")]
+    pub no_mmap: bool,
+
+    /// when to color the output: auto (only on a terminal, and not if NO_COLOR is set),
+    /// always or never
+    #[structopt(long, default_value="auto")]
+    pub color: ColorChoice,
+
+    /// change a color, like `match:fg:red`, `path:style:bold` or `line:none`;
+    /// the kinds are path, line, column, match and separator
+    #[structopt(long, number_of_values=1)]
+    pub colors: Vec<ColorSpec>,
+
+    /// print the parsed arguments to stderr
+    #[structopt(long)]
+    pub debug: bool,
+
+    /// the files to read, or directories to search recursively; `-` is stdin.
+    /// Without any, stdin is read unless it's a terminal, else the current directory is searched
     #[structopt(parse(from_os_str))]
-    pub path: PathBuf,
+    pub path: Vec<PathBuf>,
+
+    /// the name stdin goes by in the results
+    #[structopt(long, default_value="<stdin>")]
+    pub label: String,
 
-    #[structopt(short, long, parse(from_os_str), default_value="./")]
+    /// where to write the matches: `-` for stdout, a file,
+    /// or a directory (ending in `/`) to get one result file per input
+    #[structopt(short, long, parse(from_os_str), default_value="-")]
     pub output: PathBuf,
 }
\ No newline at end of file
@@ -1,42 +1,322 @@
-/// example:
-/// grrs ./ --pattern test1
+//! example:
+//! grrs ./ --pattern test1
 
-use std::io::{BufReader, BufRead};
 use std::fs;
-use std::error;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
 use std::process;
+use std::time::Instant;
+use structopt::clap::ErrorKind;
 use structopt::StructOpt;
 
 // this is how we use lib.rs
-use grrs::{type_of, Cli};
-// a workspace crate
-#[allow(unused)]
-use linked_lists::List;
+use grrs::{parallel, types, type_of, AtomicFile, Binary, Case, Cli, Colors, Error, MatcherBuilder, MmapChoice, Mode, Output, Printer, Result, Rewriter, Searcher, Sort, WalkBuilder};
 
+/// Exit status follows grep: 0 if something matched, 1 if nothing did,
+/// 2 if something went wrong, even if there were matches elsewhere,
+/// and for arguments that don't make sense.
+fn main() {
+    let args = match Cli::from_iter_safe(std::env::args_os()) {
+        Ok(args) => args,
+        Err(err) if matches!(err.kind, ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed) => err.exit(),
+        // clap would exit with 1, which means no match
+        Err(err) => {
+            eprintln!("{}", err.message);
+            process::exit(2);
+        }
+    };
+    match try_main(args) {
+        Ok(status) => process::exit(status.code()),
+        // whoever reads our output went away, e.g. `grrs ... | head`
+        Err(err) if err.is_broken_pipe() => process::exit(0),
+        Err(err) => {
+            eprintln!("grrs: {}", err);
+            process::exit(2);
+        }
+    }
+}
 
-type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
+/// How a search went, over all its inputs.
+#[derive(Debug, Default)]
+struct Status {
+    matched: bool,
+    failed: bool,
+    /// inputs searched all the way, for the JSON summary
+    searches: u64,
+    searches_with_match: u64,
+    matched_lines: u64,
+}
 
-fn main() {
-    let args = Cli::from_args();
-    if let Err(err) = try_main(args) {
-        eprintln!("{}", err);
-        process::exit(2);
+impl Status {
+    /// Take in the result of searching one input. A problem with the input
+    /// itself only gets a warning, so the search carries on with the next one.
+    fn record(&mut self, result: Result<u64>, mode: Mode) -> Result<()> {
+        match result {
+            // for `-L` a file is a hit when nothing in it matched
+            Ok(count) => {
+                self.matched |= if mode == Mode::FilesWithoutMatch { count == 0 } else { count > 0 };
+                self.searches += 1;
+                self.searches_with_match += (count > 0) as u64;
+                self.matched_lines += count;
+            }
+            Err(err) if err.is_input() => {
+                eprintln!("grrs: {}", err);
+                self.failed = true;
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+
+    /// Close JSON output with the totals.
+    fn summary(&self, printer: &Printer, out: &mut dyn Write, started: Instant) -> io::Result<()> {
+        printer.summary(out, self.searches, self.searches_with_match, self.matched_lines, started.elapsed())
+    }
+
+    fn code(&self) -> i32 {
+        if self.failed {
+            2
+        } else if self.matched {
+            0
+        } else {
+            1
+        }
     }
-    
 }
 
-fn try_main(args: Cli) -> Result<()> {
-    let content = fs::File::open(&args.path)?;
-    let reader = BufReader::new(content);
+/// Search according to the arguments.
+fn try_main(args: Cli) -> Result<Status> {
+    let started = Instant::now();
+    if args.debug {
+        eprintln!("{:?}", args);
+        eprintln!("type of args is: {:?}", type_of(&args));
+    }
+
+    let mut file_types = types::builder();
+    for def in &args.type_add {
+        file_types.add_def(def).map_err(|source| Error::Type { source })?;
+    }
+    if args.type_list {
+        types::list(&file_types, &mut io::stdout().lock())?;
+        return Ok(Status { matched: true, ..Status::default() });
+    }
+    for name in &args.file_type {
+        file_types.select(name);
+    }
+    for name in &args.type_not {
+        file_types.negate(name);
+    }
+    let file_types = file_types.build().map_err(|source| Error::Type { source })?;
+
+    let patterns = patterns(&args)?;
+    // stdin can't be searched once the patterns were read from it
+    let patterns_from_stdin = args.file.iter().any(|path| path == Path::new("-"));
+    let cwd = args.path.is_empty() && (patterns_from_stdin || io::stdin().is_terminal());
+    let roots = if !args.path.is_empty() {
+        args.path.clone()
+    } else if cwd {
+        vec![PathBuf::from(".")]
+    } else {
+        vec![PathBuf::from("-")]
+    };
+
+    let case = if args.ignore_case {
+        Case::Insensitive
+    } else if args.smart_case {
+        Case::Smart
+    } else {
+        Case::Sensitive
+    };
+    let matcher = MatcherBuilder::new()
+        .fixed_strings(args.fixed_strings)
+        .case(case)
+        .dot_all(args.multiline_dotall)
+        .word(args.word_regexp)
+        .line(args.line_regexp)
+        .build_many(&patterns)?;
+
+    let output = Output::new(&args.output);
+    let recursive = roots.len() > 1 || roots.iter().any(|root| root.is_dir());
+    let from_stdin = roots.iter().any(|root| root == Path::new("-"));
+    let mut inputs: Vec<Box<dyn Iterator<Item = Result<PathBuf>> + Send>> = Vec::new();
+    for root in roots {
+        if root.is_dir() {
+            let mut walk = WalkBuilder::new(&root);
+            walk.hidden(args.hidden)
+                .ignore(!args.no_ignore)
+                .types(file_types.clone())
+                .max_depth(args.max_depth)
+                .max_filesize(args.max_filesize);
+            for glob in &args.glob {
+                walk.glob(glob);
+            }
+            let walk = walk.build()?.map(move |path| {
+                let path = path.map_err(Error::from)?;
+                // paths under a directory nobody named read better without `./`
+                match path.strip_prefix(".") {
+                    Ok(relative) if cwd => Ok(relative.to_path_buf()),
+                    _ => Ok(path),
+                }
+            });
+            inputs.push(Box::new(walk));
+        } else {
+            inputs.push(Box::new(std::iter::once(Ok(root))));
+        }
+    }
+    let paths = inputs.into_iter().flatten();
+    // a result file per input doesn't need to say which input it's for
+    let with_path = recursive && !matches!(output, Output::Dir(_));
+    let mut printer = Printer::new(&args, with_path);
+    // result files are never colored, whatever `--color` says
+    if matches!(output, Output::Stdout) && !args.json && args.color.enabled(io::stdout().is_terminal()) {
+        let mut colors = Colors::default();
+        for spec in &args.colors {
+            colors.apply(spec);
+        }
+        printer.colors = Some(colors);
+    }
+    let (before, after) = context(&args);
+    let mode = if args.files_with_matches {
+        Mode::FilesWithMatches
+    } else if args.files_without_match {
+        Mode::FilesWithoutMatch
+    } else if args.count {
+        Mode::Count
+    } else {
+        Mode::Lines
+    };
+    let binary = if args.text { Binary::Text } else { args.binary };
+    let mmap = if args.mmap {
+        MmapChoice::Always
+    } else if args.no_mmap {
+        MmapChoice::Never
+    } else {
+        MmapChoice::Auto
+    };
+    let searcher = Searcher::new(&matcher, &printer)
+        .context(before, after)
+        .invert(args.invert_match)
+        .mode(mode)
+        .encoding(args.encoding)
+        .binary(binary)
+        .mmap(mmap)
+        .multiline(args.multiline, args.multiline_max_size)
+        .max_count(args.max_count);
+    let threads = match args.threads {
+        Some(threads) => threads,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let rewriter = args.replace.as_deref().filter(|_| args.in_place).map(|template| {
+        Rewriter::new(&matcher, template).backup(args.backup).dry_run(args.dry_run).binary(binary)
+    });
+    let label = PathBuf::from(&args.label);
+    let search = |path: &Path, out: &mut dyn Write| {
+        if let Some(rewriter) = &rewriter {
+            rewriter.rewrite(path, out)
+        } else if path == Path::new("-") {
+            searcher.search_stdin(&label, out)
+        } else {
+            searcher.search_path(path, out)
+        }
+    };
+
+    // Buffering each file's results is only worth it to put many files
+    // in order; otherwise they go straight out, as soon as they're found.
+    let streaming = !recursive || from_stdin || (args.sort == Sort::None && threads == 1);
 
-    for line in reader.lines() {
-        let line = line?;
-        if line.contains(&args.pattern) {
-            println!("{}", line);
+    let mut status = Status::default();
+    match output {
+        Output::Stdout => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            if streaming {
+                parallel::stream(paths, &mut out, search, |result| status.record(result, mode))?;
+            } else {
+                parallel::search(paths, threads, args.sort, search, |searched| match searched {
+                    Ok(searched) => {
+                        out.write_all(&searched.output)?;
+                        status.record(searched.result, mode)
+                    }
+                    Err(err) => status.record(Err(err), mode),
+                })?;
+            }
+            status.summary(&printer, &mut out, started)?;
+        }
+        Output::File(dest) => {
+            let output_error = |source| Error::Output { path: Some(dest.clone()), source };
+            let mut out = AtomicFile::create(&dest).map_err(output_error)?;
+            if streaming {
+                let searched = parallel::stream(paths, &mut out, search, |result| status.record(result, mode));
+                // what the searcher couldn't write went to the result file
+                searched.map_err(|err| match err {
+                    Error::Output { path: None, source } => output_error(source),
+                    err => err,
+                })?;
+            } else {
+                parallel::search(paths, threads, args.sort, search, |searched| match searched {
+                    Ok(searched) => {
+                        out.write_all(&searched.output).map_err(output_error)?;
+                        status.record(searched.result, mode)
+                    }
+                    Err(err) => status.record(Err(err), mode),
+                })?;
+            }
+            status.summary(&printer, &mut out, started).map_err(output_error)?;
+            out.commit().map_err(output_error)?;
+        }
+        Output::Dir(dir) => {
+            parallel::search(paths, threads, args.sort, search, |searched| {
+                let searched = match searched {
+                    Ok(searched) => searched,
+                    Err(err) => return status.record(Err(err), mode),
+                };
+                // a file that couldn't be read all the way gets no report
+                let complete = searched.result.is_ok();
+                status.record(searched.result, mode)?;
+                if complete && !searched.output.is_empty() {
+                    let input = if searched.path == Path::new("-") { &label } else { &searched.path };
+                    let dest = Output::file_for(&dir, input);
+                    let write = |buf: &[u8]| -> io::Result<()> {
+                        let mut out = AtomicFile::create(&dest)?;
+                        out.write_all(buf)?;
+                        out.commit()
+                    };
+                    write(&searched.output).map_err(|source| Error::Output { path: Some(dest.clone()), source })?;
+                }
+                Ok(())
+            })?;
         }
     }
 
-    println!("Hello, world! for {:?}", args);
-    println!("type of args is: {:?}", type_of(args));
-    Ok(())
+    Ok(status)
+}
+
+/// The patterns from `-p` and `-e`, followed by the ones from `-f` files.
+fn patterns(args: &Cli) -> Result<Vec<String>> {
+    let mut patterns: Vec<String> = args.pattern.iter().chain(&args.regexp).cloned().collect();
+    for path in &args.file {
+        let read = if path == Path::new("-") {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+        } else {
+            fs::read(path)
+        };
+        let bytes = read.map_err(|source| Error::Io { path: path.clone(), source })?;
+        // read as bytes, so a bad one can be put down to its line
+        for (i, line) in bytes.split_inclusive(|&b| b == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let line = std::str::from_utf8(line).map_err(|_| Error::Utf8 { path: path.clone(), line: i as u64 + 1 })?;
+            patterns.push(line.to_string());
+        }
+    }
+    Ok(patterns)
+}
+
+/// Lines of context to show (before, after) a match;
+/// `-A` and `-B` take precedence over `-C`.
+fn context(args: &Cli) -> (usize, usize) {
+    let both = args.context.unwrap_or(0);
+    (args.before_context.unwrap_or(both), args.after_context.unwrap_or(both))
 }
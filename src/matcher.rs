@@ -0,0 +1,470 @@
+use std::borrow::Cow;
+
+use aho_corasick::{AhoCorasick, Input, MatchKind};
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use regex_syntax::ParserBuilder;
+
+use crate::Error;
+
+/// Decides whether a line matches the patterns given on the command line,
+/// which it does if any one of them matches.
+/// Patterns are compiled as regular expressions, unless fixed strings
+/// are asked for, in which case they're matched as plain substrings.
+#[derive(Debug)]
+pub struct Matcher {
+    engine: Engine,
+    line_local: bool,
+}
+
+#[derive(Debug)]
+enum Engine {
+    Regex {
+        re: Regex,
+        /// the same pattern, for finding candidate lines in a whole file at once
+        buf_re: Regex,
+    },
+    /// Fixed strings, all looked for in a single pass, however many there are.
+    Literals(AhoCorasick),
+}
+
+/// How the case of letters is treated when matching.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Case {
+    Sensitive,
+    Insensitive,
+    /// insensitive, unless the pattern has an uppercase letter in it
+    Smart,
+}
+
+/// Options for compiling a `Matcher`.
+#[derive(Debug, Clone)]
+pub struct MatcherBuilder {
+    fixed_strings: bool,
+    case: Case,
+    dot_all: bool,
+    word: bool,
+    line: bool,
+}
+
+impl Default for MatcherBuilder {
+    fn default() -> Self {
+        MatcherBuilder { fixed_strings: false, case: Case::Sensitive, dot_all: false, word: false, line: false }
+    }
+}
+
+impl MatcherBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match the pattern as a literal string instead of a regular expression.
+    pub fn fixed_strings(&mut self, yes: bool) -> &mut Self {
+        self.fixed_strings = yes;
+        self
+    }
+
+    pub fn case(&mut self, case: Case) -> &mut Self {
+        self.case = case;
+        self
+    }
+
+    /// Let `.` match line breaks too, for matches that run over several lines.
+    pub fn dot_all(&mut self, yes: bool) -> &mut Self {
+        self.dot_all = yes;
+        self
+    }
+
+    /// Only match whole words: what comes right before and after a match
+    /// can't be a letter, digit or underscore, in any script.
+    pub fn word(&mut self, yes: bool) -> &mut Self {
+        self.word = yes;
+        self
+    }
+
+    /// Only match whole lines. Wins over `word`.
+    pub fn line(&mut self, yes: bool) -> &mut Self {
+        self.line = yes;
+        self
+    }
+
+    pub fn build(&self, pattern: &str) -> Result<Matcher, Error> {
+        self.build_many(&[pattern])
+    }
+
+    /// Compile a matcher for lines that any of the patterns match.
+    /// With none at all, nothing matches.
+    pub fn build_many<P: AsRef<str>>(&self, patterns: &[P]) -> Result<Matcher, Error> {
+        let patterns: Vec<&str> = patterns.iter().map(AsRef::as_ref).collect();
+        let ignore_case = match self.case {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => !patterns.iter().any(|pattern| has_uppercase(pattern, self.fixed_strings)),
+        };
+
+        // Aho-Corasick only folds ASCII case, the regex engine folds Unicode
+        // it has no notion of words or lines either
+        let literals = self.fixed_strings
+            && !self.word
+            && !self.line
+            && (!ignore_case || patterns.iter().all(|pattern| pattern.is_ascii()));
+        if literals || patterns.is_empty() {
+            let ac = AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostFirst)
+                .ascii_case_insensitive(ignore_case)
+                .build(&patterns);
+            if let Ok(ac) = ac {
+                return Ok(Matcher { engine: Engine::Literals(ac), line_local: true });
+            }
+        }
+
+        let escaped: Vec<String> = patterns
+            .iter()
+            .map(|pattern| if self.fixed_strings { regex::escape(pattern) } else { pattern.to_string() })
+            .collect();
+        let mut combined = match &escaped[..] {
+            [one] => one.clone(),
+            many => many.iter().map(|pattern| format!("(?:{})", pattern)).collect::<Vec<_>>().join("|"),
+        };
+        if self.line {
+            combined = format!("^(?:{})$", combined);
+        } else if self.word {
+            // unlike `\b`, these hold next to a pattern's own non-word ends,
+            // so `-w -F .foo` still finds `a .foo b`
+            combined = format!(r"\b{{start-half}}(?:{})\b{{end-half}}", combined);
+        }
+        // Unicode is on, so case folding covers more than ASCII: `é` finds `É`.
+        // The regex engine only does simple (one to one) folding though,
+        // so `ß` won't find `SS`.
+        let build = |pattern: &str, multi_line| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .unicode(true)
+                .multi_line(multi_line)
+                .crlf(multi_line)
+                .dot_matches_new_line(self.dot_all)
+                .build()
+        };
+        let re = match build(&combined, false) {
+            Ok(re) => re,
+            Err(source) => {
+                // blame the pattern that's wrong, rather than all of them together
+                let (pattern, source) = patterns
+                    .iter()
+                    .zip(&escaped)
+                    .find_map(|(pattern, escaped)| build(escaped, false).err().map(|err| (pattern.to_string(), err)))
+                    .unwrap_or_else(|| (patterns.join("\n"), source));
+                return Err(Error::Pattern { pattern, source });
+            }
+        };
+        let buf_re = build(&combined, true).map_err(|source| Error::Pattern { pattern: patterns.join("\n"), source })?;
+        // parsed the way `buf_re` was built, to see what it makes of the pattern
+        let hir = ParserBuilder::new()
+            .case_insensitive(ignore_case)
+            .unicode(true)
+            .utf8(false)
+            .multi_line(true)
+            .crlf(true)
+            .dot_matches_new_line(self.dot_all)
+            .build()
+            .parse(&combined);
+        let line_local = hir.is_ok_and(|hir| is_line_local(&hir));
+        Ok(Matcher { engine: Engine::Regex { re, buf_re }, line_local })
+    }
+}
+
+/// Whether a pattern, as `buf_re` sees it, only ever matches within a line,
+/// and means the same there as it does to `re` on the line alone.
+/// `\A` and `\z`, and `^` and `$` with the `m` or `R` flags turned off,
+/// are about the ends of the whole buffer or bare `\n`s, not of a line.
+/// A pattern that can match a line break could run on far past the line
+/// it starts in, and be found again from every line it covers.
+fn is_line_local(hir: &Hir) -> bool {
+    let looks = hir.properties().look_set();
+    let anchored = [Look::Start, Look::End, Look::StartLF, Look::EndLF].iter().any(|&look| looks.contains(look));
+    !anchored && !matches_newline(hir)
+}
+
+/// Whether anything in the pattern can match a `\n`.
+fn matches_newline(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => false,
+        HirKind::Literal(literal) => literal.0.contains(&b'\n'),
+        HirKind::Class(Class::Unicode(class)) => class.ranges().iter().any(|range| range.start() <= '\n' && '\n' <= range.end()),
+        HirKind::Class(Class::Bytes(class)) => class.ranges().iter().any(|range| range.start() <= b'\n' && b'\n' <= range.end()),
+        HirKind::Repetition(repetition) => matches_newline(&repetition.sub),
+        HirKind::Capture(capture) => matches_newline(&capture.sub),
+        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => hirs.iter().any(matches_newline),
+    }
+}
+
+/// Whether the pattern spells out an uppercase letter.
+/// Escapes like `\W` or `\p{Lu}` are about classes of characters,
+/// not the case of the pattern, so they don't count.
+fn has_uppercase(pattern: &str, fixed_strings: bool) -> bool {
+    if fixed_strings {
+        return pattern.chars().any(char::is_uppercase);
+    }
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('p') | Some('P') => {
+                    if chars.clone().next() == Some('{') {
+                        chars.by_ref().find(|&c| c == '}');
+                    } else {
+                        chars.next();
+                    }
+                }
+                _ => {}
+            }
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+impl Matcher {
+    pub fn new(pattern: &str) -> Result<Self, Error> {
+        MatcherBuilder::new().build(pattern)
+    }
+
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        match &self.engine {
+            Engine::Regex { re, .. } => re.is_match(line),
+            Engine::Literals(ac) => ac.is_match(line),
+        }
+    }
+
+    /// Find the first match in a buffer of many lines, at or after `start`.
+    /// `^` and `$` match at line breaks, but a match can run across them,
+    /// so it only says which line to check with `is_match`.
+    pub fn find_at(&self, buf: &[u8], start: usize) -> Option<(usize, usize)> {
+        match &self.engine {
+            Engine::Regex { buf_re, .. } => buf_re.find_at(buf, start).map(|m| (m.start(), m.end())),
+            Engine::Literals(ac) => ac.find(Input::new(buf).range(start..)).map(|m| (m.start(), m.end())),
+        }
+    }
+
+    /// Whether every line `is_match` accepts has a match `find_at` finds,
+    /// and every match `find_at` finds is inside one line.
+    pub fn is_line_local(&self) -> bool {
+        self.line_local
+    }
+
+    /// The line with every match replaced by `template`, in which `$1` or
+    /// `${name}` stand for what a capture group matched, `$0` for the whole
+    /// match and `$$` for a `$`. Fixed strings only have the whole match.
+    pub fn replace<'a>(&self, line: &'a [u8], template: &str) -> Cow<'a, [u8]> {
+        match &self.engine {
+            Engine::Regex { re, .. } => re.replace_all(line, template.as_bytes()),
+            Engine::Literals(ac) => {
+                let mut matches = ac.find_iter(line).peekable();
+                if matches.peek().is_none() {
+                    return Cow::Borrowed(line);
+                }
+                let mut replaced = Vec::new();
+                let mut last = 0;
+                for m in matches {
+                    replaced.extend_from_slice(&line[last..m.start()]);
+                    expand_literal(template, &line[m.start()..m.end()], &mut replaced);
+                    last = m.end();
+                }
+                replaced.extend_from_slice(&line[last..]);
+                Cow::Owned(replaced)
+            }
+        }
+    }
+
+    /// For every match in the line, the byte range of each capture group
+    /// that took part, the whole match first. Fixed strings have only that.
+    pub fn captures_iter<'a>(&'a self, line: &'a [u8]) -> impl Iterator<Item = Vec<Option<(usize, usize)>>> + 'a {
+        let captures: Box<dyn Iterator<Item = Vec<Option<(usize, usize)>>> + 'a> = match &self.engine {
+            Engine::Regex { re, .. } => Box::new(
+                re.captures_iter(line)
+                    .map(|caps| caps.iter().map(|group| group.map(|m| (m.start(), m.end()))).collect()),
+            ),
+            Engine::Literals(ac) => Box::new(ac.find_iter(line).map(|m| vec![Some((m.start(), m.end()))])),
+        };
+        captures
+    }
+
+    /// The number of the capture group with the name, if there is one.
+    pub fn group_index(&self, name: &str) -> Option<usize> {
+        match &self.engine {
+            Engine::Regex { re, .. } => re.capture_names().position(|group| group == Some(name)),
+            Engine::Literals(_) => None,
+        }
+    }
+
+    /// The byte ranges of every match in the line, as `(start, end)`.
+    pub fn find_iter<'a>(&'a self, line: &'a [u8]) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let matches: Box<dyn Iterator<Item = (usize, usize)> + 'a> = match &self.engine {
+            Engine::Regex { re, .. } => Box::new(re.find_iter(line).map(|m| (m.start(), m.end()))),
+            Engine::Literals(ac) => Box::new(ac.find_iter(line).map(|m| (m.start(), m.end()))),
+        };
+        matches
+    }
+}
+
+/// Expand a replacement template the way the regex engine does,
+/// for a match without capture groups: any group other than 0 is empty.
+fn expand_literal(template: &str, matched: &[u8], out: &mut Vec<u8>) {
+    let mut rest = template;
+    while let Some(i) = rest.find('$') {
+        out.extend_from_slice(&rest.as_bytes()[..i]);
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push(b'$');
+            rest = after;
+            continue;
+        }
+        let (name, after) = match rest.strip_prefix('{').and_then(|braced| braced.split_once('}')) {
+            Some((name, after)) => (name, after),
+            None => {
+                let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                if end == 0 {
+                    // a lone `$` stays as it is
+                    out.push(b'$');
+                    continue;
+                }
+                (&rest[..end], &rest[end..])
+            }
+        };
+        if name == "0" {
+            out.extend_from_slice(matched);
+        }
+        rest = after;
+    }
+    out.extend_from_slice(rest.as_bytes());
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Case, Matcher, MatcherBuilder};
+
+    #[test]
+    fn regex() {
+        let m = Matcher::new(r"ba[rz]: \d+").unwrap();
+        assert!(m.is_match(b"bar: 20"));
+        assert!(m.is_match(b"baz: 30"));
+        assert!(!m.is_match(b"foo: 10"));
+    }
+
+    #[test]
+    fn fixed_strings() {
+        let m = MatcherBuilder::new().fixed_strings(true).build("a.b(").unwrap();
+        assert!(m.is_match(b"xa.b(y"));
+        assert!(!m.is_match(b"axb("));
+    }
+
+    #[test]
+    fn many() {
+        let builder = MatcherBuilder::new();
+        let m = builder.build_many(&["foo", r"ba[rz]"]).unwrap();
+        assert!(m.is_match(b"foo: 10"));
+        assert!(m.is_match(b"baz: 30"));
+        assert!(!m.is_match(b"qux: 40"));
+        let m = builder.build_many(&[r"\d{3}"; 0]).unwrap();
+        assert!(!m.is_match(b"anything"));
+        // the pattern that's wrong is the one named
+        let err = builder.build_many(&["foo", "ba(r"]).unwrap_err();
+        assert!(err.to_string().starts_with("invalid pattern \"ba(r\""));
+    }
+
+    #[test]
+    fn many_fixed_strings() {
+        let tokens: Vec<String> = (0..5000).map(|n| format!("tok{}en", n)).collect();
+        let m = MatcherBuilder::new().fixed_strings(true).build_many(&tokens).unwrap();
+        assert!(m.is_match(b"a tok4321en here"));
+        assert!(!m.is_match(b"a tok4321 here"));
+        assert_eq!(m.find_iter(b"tok1en tok20en").collect::<Vec<_>>(), [(0, 6), (7, 14)]);
+        assert_eq!(m.find_at(b"tok1en\ntok2en", 1), Some((7, 13)));
+
+        let m = MatcherBuilder::new().fixed_strings(true).case(Case::Smart).build_many(&["tok", "é"]).unwrap();
+        assert!(m.is_match("TOK".as_bytes()));
+        assert!(m.is_match("É".as_bytes()));
+    }
+
+    #[test]
+    fn word() {
+        let mut builder = MatcherBuilder::new();
+        builder.word(true);
+        let m = builder.build("bar").unwrap();
+        assert!(m.is_match(b"bar: 20"));
+        assert!(!m.is_match(b"barbaz: 20"));
+        assert!(!m.is_match("ébar".as_bytes()));
+        assert_eq!(m.find_iter(b"barbar bar").collect::<Vec<_>>(), [(7, 10)]);
+        let m = builder.fixed_strings(true).build_many(&[".foo", "a.b"]).unwrap();
+        assert!(m.is_match(b"x .foo y"));
+        assert!(m.is_match(b"(a.b)"));
+        assert!(!m.is_match(b"xa.b"));
+    }
+
+    #[test]
+    fn line() {
+        let mut builder = MatcherBuilder::new();
+        builder.line(true);
+        assert!(builder.build(r"bar: \d+").unwrap().is_match(b"bar: 20"));
+        assert!(!builder.build("bar").unwrap().is_match(b"bar: 20"));
+        let m = builder.fixed_strings(true).build_many(&["foo", "bar: 20"]).unwrap();
+        assert!(m.is_match(b"bar: 20"));
+        assert!(!m.is_match(b"foo: 10"));
+        assert_eq!(m.find_at(b"foo: 10\r\nbar: 20\r\n", 0), Some((9, 16)));
+    }
+
+    #[test]
+    fn replace() {
+        let m = Matcher::new(r"(?P<key>\w+): (\d+)").unwrap();
+        assert_eq!(&*m.replace(b"bar: 20, baz: 30", "$2=${key}"), b"20=bar, 30=baz");
+        assert_eq!(&*m.replace(b"nothing", "$1"), b"nothing");
+        let m = MatcherBuilder::new().fixed_strings(true).build_many(&["ba", "fo"]).unwrap();
+        assert_eq!(&*m.replace(b"foo bar", "<$0$1$$${0}>"), b"<fo$fo>o <ba$ba>r");
+        assert_eq!(&*m.replace(b"qux", "x"), b"qux");
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let err = Matcher::new("foo(").unwrap_err();
+        // the syntax error carries a caret under the offending position
+        assert!(err.to_string().contains('^'));
+    }
+
+    #[test]
+    fn find_at() {
+        let m = Matcher::new(r"^ba\w: \d+$").unwrap();
+        let buf = b"foo: 10\r\nbar: 20\r\nbaz: 30";
+        assert_eq!(m.find_at(buf, 0), Some((9, 16)));
+        assert_eq!(m.find_at(buf, 16), Some((18, 25)));
+        assert!(m.is_line_local());
+        assert!(!Matcher::new(r"\Afoo").unwrap().is_line_local());
+        // anchors with `m` or `R` off mean something else in a whole buffer
+        assert!(!Matcher::new(r"(?-m)bar$").unwrap().is_line_local());
+        assert!(!Matcher::new(r"(?-R)bar$").unwrap().is_line_local());
+        // so do patterns that can run past the end of a line
+        assert!(!Matcher::new(r#""[^"]*foo"#).unwrap().is_line_local());
+        assert!(!Matcher::new(r"foo\sbar").unwrap().is_line_local());
+        assert!(MatcherBuilder::new().line(true).build("bar").unwrap().is_line_local());
+    }
+
+    #[test]
+    fn ignore_case() {
+        let m = MatcherBuilder::new().case(Case::Insensitive).build("ÉTÉ").unwrap();
+        assert!(m.is_match("un été chaud".as_bytes()));
+        let m = MatcherBuilder::new().case(Case::Insensitive).fixed_strings(true).build("a.B").unwrap();
+        assert!(m.is_match(b"A.b"));
+        assert!(!m.is_match(b"AxB"));
+    }
+
+    #[test]
+    fn smart_case() {
+        let mut builder = MatcherBuilder::new();
+        builder.case(Case::Smart);
+        assert!(builder.build("foo").unwrap().is_match(b"FOO: 10"));
+        assert!(!builder.build("Foo").unwrap().is_match(b"FOO: 10"));
+        // escapes for character classes don't make the pattern case sensitive
+        assert!(builder.build(r"\Wfoo\p{Greek}?").unwrap().is_match(b" FOO"));
+        assert!(!builder.fixed_strings(true).build("Foo").unwrap().is_match(b"FOO"));
+    }
+}
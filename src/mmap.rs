@@ -0,0 +1,237 @@
+//! Memory maps that survive their file shrinking underneath them.
+//!
+//! Reading a page of a mapping past the end of its file raises `SIGBUS`,
+//! which would kill the process. While a `Map` is alive, a `SIGBUS` handler
+//! swaps the missing page for one of zeros and flags the map as truncated,
+//! so the search finishes and the caller can tell its results are suspect.
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+
+use memmap2::Mmap;
+
+/// A read-only map of a whole file.
+pub struct Map {
+    mmap: Mmap,
+    #[cfg(unix)]
+    slot: &'static guard::Slot,
+}
+
+impl Map {
+    /// Map the file, or `None` if it can't be done safely,
+    /// in which case it should just be read.
+    #[cfg(unix)]
+    pub fn open(file: &File) -> io::Result<Option<Map>> {
+        if file.metadata()?.len() == 0 {
+            return Ok(None);
+        }
+        // Safety: another process could change the file while it's mapped.
+        // Changes only affect what's read, and truncation is caught below.
+        let mmap = unsafe { Mmap::map(file)? };
+        let start = mmap.as_ptr() as usize;
+        Ok(guard::register(start, start + mmap.len()).map(|slot| Map { mmap, slot }))
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(_: &File) -> io::Result<Option<Map>> {
+        Ok(None)
+    }
+
+    /// Whether part of the file went missing while it was mapped.
+    /// Those bytes read as zeros.
+    pub fn truncated(&self) -> bool {
+        #[cfg(unix)]
+        return self.slot.truncated();
+        #[cfg(not(unix))]
+        return false;
+    }
+}
+
+impl Deref for Map {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Map {
+    fn drop(&mut self) {
+        self.slot.release();
+    }
+}
+
+#[cfg(unix)]
+mod guard {
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+    use std::sync::{Once, OnceLock};
+
+    /// How many maps can be alive at once; more than that get read instead.
+    const SLOTS: usize = 128;
+
+    /// The address range of a live map.
+    pub struct Slot {
+        used: AtomicBool,
+        start: AtomicUsize,
+        end: AtomicUsize,
+        truncated: AtomicBool,
+    }
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const FREE: Slot = Slot {
+        used: AtomicBool::new(false),
+        start: AtomicUsize::new(0),
+        end: AtomicUsize::new(0),
+        truncated: AtomicBool::new(false),
+    };
+
+    static MAPS: [Slot; SLOTS] = [FREE; SLOTS];
+    static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+    static INSTALL: Once = Once::new();
+    /// The SIGBUS handler that was there before ours, for the faults that aren't ours.
+    static PREVIOUS: OnceLock<libc::sigaction> = OnceLock::new();
+
+    impl Slot {
+        pub fn truncated(&self) -> bool {
+            self.truncated.load(SeqCst)
+        }
+
+        pub fn release(&self) {
+            self.start.store(0, SeqCst);
+            self.end.store(0, SeqCst);
+            self.used.store(false, SeqCst);
+        }
+    }
+
+    /// Watch `start..end` for pages that went missing.
+    pub fn register(start: usize, end: usize) -> Option<&'static Slot> {
+        INSTALL.call_once(install);
+        let slot = MAPS
+            .iter()
+            .find(|slot| slot.used.compare_exchange(false, true, SeqCst, SeqCst).is_ok())?;
+        slot.truncated.store(false, SeqCst);
+        slot.end.store(end, SeqCst);
+        slot.start.store(start, SeqCst);
+        Some(slot)
+    }
+
+    fn install() {
+        unsafe {
+            PAGE_SIZE.store(libc::sysconf(libc::_SC_PAGESIZE) as usize, SeqCst);
+            // remember what was there first, before any fault can come to us
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(libc::SIGBUS, ptr::null(), &mut previous) != 0 {
+                return;
+            }
+            let _ = PREVIOUS.set(previous);
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_sigbus as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGBUS, &action, ptr::null_mut());
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn fault_address(info: *mut libc::siginfo_t) -> usize {
+        (*info).si_addr() as usize
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    unsafe fn fault_address(info: *mut libc::siginfo_t) -> usize {
+        (*info).si_addr as usize
+    }
+
+    extern "C" fn on_sigbus(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+        let addr = unsafe { fault_address(info) };
+        let page_size = PAGE_SIZE.load(SeqCst);
+        for slot in MAPS.iter() {
+            let start = slot.start.load(SeqCst);
+            if start == 0 || addr < start || addr >= slot.end.load(SeqCst) {
+                continue;
+            }
+            // Put a page of zeros where the file used to be; the read that
+            // faulted is retried once the handler returns, and succeeds.
+            let page = (addr & !(page_size - 1)) as *mut libc::c_void;
+            // Safety: mmap isn't on POSIX's list of async-signal-safe calls,
+            // but on Linux, Android, macOS and the BSDs it's a plain system
+            // call that takes no locks in libc, so it can't deadlock with the
+            // code the signal interrupted. MAP_FIXED only ever replaces a page
+            // inside a range registered by a live `Map`, which keeps its slot
+            // until the mapping itself is gone, so nothing else's memory is
+            // touched; the page is read-only, like the map it stands in for.
+            let zeros = unsafe {
+                libc::mmap(
+                    page,
+                    page_size,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
+                    -1,
+                    0,
+                )
+            };
+            if zeros != libc::MAP_FAILED {
+                slot.truncated.store(true, SeqCst);
+                return;
+            }
+        }
+        // Not one of ours: hand it to the handler that was there before,
+        // or if that was no handler at all, put its action back, so the
+        // retried access goes the way it would have without us.
+        let previous = match PREVIOUS.get() {
+            Some(previous) => previous,
+            None => return unsafe { restore(&default_action()) },
+        };
+        match previous.sa_sigaction {
+            libc::SIG_DFL | libc::SIG_IGN => unsafe { restore(previous) },
+            handler if previous.sa_flags & libc::SA_SIGINFO != 0 => {
+                let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                    unsafe { std::mem::transmute(handler) };
+                handler(signal, info, context);
+            }
+            handler => {
+                let handler: extern "C" fn(libc::c_int) = unsafe { std::mem::transmute(handler) };
+                handler(signal);
+            }
+        }
+    }
+
+    fn default_action() -> libc::sigaction {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = libc::SIG_DFL;
+        action
+    }
+
+    unsafe fn restore(action: &libc::sigaction) {
+        libc::sigaction(libc::SIGBUS, action, ptr::null_mut());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::Map;
+    use std::fs::{self, OpenOptions};
+
+    #[test]
+    fn truncated_while_mapped() {
+        let path = std::env::temp_dir().join(format!("grrs-mmap-{}", std::process::id()));
+        fs::write(&path, vec![b'x'; 1 << 16]).unwrap();
+
+        let file = fs::File::open(&path).unwrap();
+        let map = Map::open(&file).unwrap().unwrap();
+        assert_eq!(map[100], b'x');
+        assert!(!map.truncated());
+
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(10).unwrap();
+        // reading past the new end doesn't crash, it reads zeros
+        assert_eq!(map[1 << 15], 0);
+        assert_eq!(map[5], b'x');
+        assert!(map.truncated());
+
+        drop(map);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Numbers the temporary files, so two written to the same destination
+/// at once, as with `--in-place a a`, don't share one.
+static NEXT_TMP: AtomicUsize = AtomicUsize::new(0);
+
+/// Where search results go, as picked by `-o/--output`.
+#[derive(Debug, PartialEq)]
+pub enum Output {
+    /// `-` or no `--output` at all
+    Stdout,
+    /// all results in a single file
+    File(PathBuf),
+    /// one result file per searched input, laid out like the inputs
+    Dir(PathBuf),
+}
+
+impl Output {
+    pub fn new(path: &Path) -> Self {
+        if path == Path::new("-") {
+            Output::Stdout
+        } else if path.is_dir() || path.to_string_lossy().ends_with(std::path::is_separator) {
+            Output::Dir(path.to_path_buf())
+        } else {
+            Output::File(path.to_path_buf())
+        }
+    }
+
+    /// The result file for `input` when writing to a directory,
+    /// e.g. `out/` and `./src/lib.rs` give `out/src/lib.rs`.
+    pub fn file_for(dir: &Path, input: &Path) -> PathBuf {
+        let relative: PathBuf = input
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        dir.join(relative)
+    }
+}
+
+/// A file that only shows up at its destination once it's complete.
+/// Everything is written to a temporary file next to the destination,
+/// which is renamed over it by `commit`. Dropping without committing
+/// throws the temporary file away, so readers never see half a report.
+pub struct AtomicFile {
+    tmp: PathBuf,
+    dest: PathBuf,
+    file: Option<File>,
+}
+
+impl AtomicFile {
+    pub fn create(dest: &Path) -> io::Result<Self> {
+        let dir = match dest.parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(dir)?;
+        let name = dest
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name"))?;
+        let n = NEXT_TMP.fetch_add(1, Ordering::Relaxed);
+        let tmp = dir.join(format!(".{}.grrs-{}-{}.tmp", name.to_string_lossy(), process::id(), n));
+        let file = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        Ok(AtomicFile { tmp, dest: dest.to_path_buf(), file: Some(file) })
+    }
+
+    /// Give the file the permissions it will have at its destination.
+    pub fn set_permissions(&self, permissions: fs::Permissions) -> io::Result<()> {
+        self.file.as_ref().expect("set_permissions after commit").set_permissions(permissions)
+    }
+
+    pub fn commit(mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.tmp, &self.dest)
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.as_mut().expect("write after commit").write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().expect("flush after commit").flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // still holding the file means `commit` never ran
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AtomicFile, Output};
+    use std::fs;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn output_kind() {
+        assert_eq!(Output::new(Path::new("-")), Output::Stdout);
+        assert_eq!(Output::new(Path::new("src")), Output::Dir(PathBuf::from("src")));
+        assert_eq!(Output::new(Path::new("out/")), Output::Dir(PathBuf::from("out/")));
+        assert_eq!(Output::new(Path::new("out.txt")), Output::File(PathBuf::from("out.txt")));
+    }
+
+    #[test]
+    fn file_for() {
+        assert_eq!(
+            Output::file_for(Path::new("out"), Path::new("./src/lib.rs")),
+            PathBuf::from("out/src/lib.rs")
+        );
+        assert_eq!(
+            Output::file_for(Path::new("out"), Path::new("/tmp/../a.txt")),
+            PathBuf::from("out/tmp/a.txt")
+        );
+    }
+
+    #[test]
+    fn atomic_file() {
+        let dir = std::env::temp_dir().join(format!("grrs-atomic-{}", std::process::id()));
+        let dest = dir.join("report.txt");
+
+        let mut file = AtomicFile::create(&dest).unwrap();
+        file.write_all(b"foo: 10\n").unwrap();
+        assert!(!dest.exists());
+        file.commit().unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "foo: 10\n");
+
+        // an abandoned write leaves the previous report alone
+        let mut file = AtomicFile::create(&dest).unwrap();
+        file.write_all(b"bar: 20\n").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "foo: 10\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // two at once for the same destination each get their own temporary file
+        let mut first = AtomicFile::create(&dest).unwrap();
+        let mut second = AtomicFile::create(&dest).unwrap();
+        first.write_all(b"first\n").unwrap();
+        second.write_all(b"second\n").unwrap();
+        first.commit().unwrap();
+        second.commit().unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "second\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::sync_channel;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::Result;
+
+/// Files waiting for a worker, and results waiting to be printed, at most.
+/// Together with the window below, keeps memory flat however big the tree is.
+const QUEUE_LEN: usize = 64;
+
+/// How far past the next file to print the files handed out may go,
+/// beyond one per worker. Results that finish ahead of their turn are
+/// held until it comes, so this bounds how many are held at once.
+const WINDOW_LEN: usize = 64;
+
+/// The order results are printed in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sort {
+    /// by path, the same on every run
+    Path,
+    /// as soon as each file is done, for speed
+    None,
+}
+
+impl FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(Sort::Path),
+            "none" => Ok(Sort::None),
+            _ => Err(format!("unknown sort {:?}, expected path or none", s)),
+        }
+    }
+}
+
+/// One file's worth of results.
+#[derive(Debug)]
+pub struct Searched {
+    pub path: PathBuf,
+    /// how many lines were selected
+    pub result: Result<u64>,
+    /// what was printed for the file
+    pub output: Vec<u8>,
+}
+
+/// How far the files handed out to the workers may get ahead of printing.
+struct Window {
+    /// the first index not to hand out yet, or `None` once anything goes
+    limit: Mutex<Option<usize>>,
+    moved: Condvar,
+}
+
+impl Window {
+    /// Wait until file `index` can be handed out.
+    fn wait_for(&self, index: usize) {
+        let mut limit = self.limit.lock().unwrap();
+        while limit.is_some_and(|limit| index >= limit) {
+            limit = self.moved.wait(limit).unwrap();
+        }
+    }
+
+    fn set(&self, limit: Option<usize>) {
+        *self.limit.lock().unwrap() = limit;
+        self.moved.notify_all();
+    }
+}
+
+/// Search `paths` one after the other on the calling thread, writing
+/// straight to `out`, so results show up as soon as they're found,
+/// even from an input that never ends, like `tail -f`. Each result goes
+/// to `record`, whose first error stops the search.
+pub fn stream<I, S, R>(paths: I, out: &mut dyn Write, search: S, mut record: R) -> Result<()>
+where
+    I: Iterator<Item = Result<PathBuf>>,
+    S: Fn(&Path, &mut dyn Write) -> Result<u64>,
+    R: FnMut(Result<u64>) -> Result<()>,
+{
+    for path in paths {
+        record(path.and_then(|path| search(&path, out)))?;
+    }
+    Ok(())
+}
+
+/// Search `paths` on a pool of `threads` workers, handing each file's results
+/// to `emit` on the calling thread. The paths come through a bounded queue,
+/// so the walk only runs a little ahead of the searching, and no further
+/// ahead of printing than `WINDOW_LEN` files. With `Sort::Path` results come
+/// out in the order of `paths`, which a `Walk` yields sorted.
+/// The first error from `emit` stops the search.
+pub fn search<I, S, E>(paths: I, threads: usize, sort: Sort, search: S, mut emit: E) -> Result<()>
+where
+    I: Iterator<Item = Result<PathBuf>> + Send,
+    S: Fn(&Path, &mut dyn Write) -> Result<u64> + Sync,
+    E: FnMut(Result<Searched>) -> Result<()>,
+{
+    let threads = threads.max(1);
+    let search = &search;
+    let ahead = WINDOW_LEN + threads;
+    let window = Window { limit: Mutex::new(Some(ahead)), moved: Condvar::new() };
+    let window = &window;
+
+    thread::scope(|scope| {
+        let (job_tx, job_rx) = sync_channel(QUEUE_LEN);
+        let (result_tx, result_rx) = sync_channel(QUEUE_LEN);
+
+        scope.spawn(move || {
+            for job in paths.enumerate() {
+                window.wait_for(job.0);
+                // the workers are gone, so nobody wants the rest
+                if job_tx.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // only the workers hold on to the queue, so the walk above stops
+        // once they all have
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        for _ in 0..threads {
+            let job_rx = Arc::clone(&job_rx);
+            let result_tx = result_tx.clone();
+            scope.spawn(move || loop {
+                let job = job_rx.lock().unwrap().recv();
+                let (index, path) = match job {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                let searched = path.map(|path| {
+                    let mut output = Vec::new();
+                    let result = search(&path, &mut output);
+                    Searched { path, result, output }
+                });
+                if result_tx.send((index, searched)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(job_rx);
+        drop(result_tx);
+
+        let print = || {
+            // results that finished ahead of their turn
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            for (index, searched) in result_rx {
+                if sort == Sort::None {
+                    emit(searched)?;
+                    next += 1;
+                    window.set(Some(next + ahead));
+                    continue;
+                }
+                pending.insert(index, searched);
+                while let Some(searched) = pending.remove(&next) {
+                    emit(searched)?;
+                    next += 1;
+                    window.set(Some(next + ahead));
+                }
+            }
+            Ok(())
+        };
+        let printed = print();
+        // nothing more gets printed, so the walk mustn't wait on it
+        window.set(None);
+        printed
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{search, stream, Sort, WINDOW_LEN};
+    use crate::Error;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn paths(n: usize) -> impl Iterator<Item = crate::Result<PathBuf>> + Send {
+        (0..n).map(|i| Ok(PathBuf::from(format!("{:03}", i))))
+    }
+
+    #[test]
+    fn sorted() {
+        let mut seen = Vec::new();
+        search(
+            paths(200),
+            8,
+            Sort::Path,
+            |path, out| {
+                // later files finish first
+                let i: u64 = path.to_str().unwrap().parse().unwrap();
+                thread::sleep(Duration::from_micros(200 - i));
+                writeln!(out, "{}", i)?;
+                Ok(i)
+            },
+            |searched| {
+                let searched = searched?;
+                seen.push(String::from_utf8(searched.output).unwrap());
+                Ok(())
+            },
+        )
+        .unwrap();
+        let expected: Vec<_> = (0..200).map(|i| format!("{}\n", i)).collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn window() {
+        // while the first file takes its time, the others only get so far ahead
+        let furthest = AtomicUsize::new(0);
+        let mut ahead = 0;
+        search(
+            paths(1000),
+            4,
+            Sort::Path,
+            |path, _| {
+                let i: usize = path.to_str().unwrap().parse().unwrap();
+                if i == 0 {
+                    thread::sleep(Duration::from_millis(100));
+                    return Ok(furthest.load(Ordering::SeqCst) as u64);
+                }
+                furthest.fetch_max(i, Ordering::SeqCst);
+                Ok(0)
+            },
+            |searched| {
+                let searched = searched?;
+                if searched.path == Path::new("000") {
+                    ahead = searched.result?;
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert!(ahead > 0 && ahead < (WINDOW_LEN + 4) as u64, "{}", ahead);
+    }
+
+    #[test]
+    fn stream_writes_through() {
+        let mut out = Vec::new();
+        let mut counts = Vec::new();
+        stream(paths(3), &mut out, |path, out| {
+            writeln!(out, "{}", path.display())?;
+            Ok(1)
+        }, |count| {
+            counts.push(count?);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(out, b"000\n001\n002\n");
+        assert_eq!(counts, [1, 1, 1]);
+    }
+
+    #[test]
+    fn unsorted() {
+        let mut seen = Vec::new();
+        search(paths(100), 4, Sort::None, |_, _| Ok(1), |searched| {
+            seen.push(searched?.path);
+            Ok(())
+        })
+        .unwrap();
+        seen.sort();
+        assert_eq!(seen, paths(100).map(Result::unwrap).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn emit_error_stops() {
+        // far more files than fit in the queues, and nothing hangs
+        let mut emitted = 0;
+        let result = search(paths(10_000), 2, Sort::Path, |_, _| Ok(0), |_| {
+            emitted += 1;
+            Err(Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe")))
+        });
+        assert!(result.unwrap_err().is_broken_pipe());
+        assert_eq!(emitted, 1);
+    }
+}
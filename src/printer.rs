@@ -0,0 +1,454 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::color::{Colors, Style};
+use crate::format::Format;
+use crate::json;
+use crate::{Cli, Matcher};
+
+/// Formats matching lines, with whatever location prefixes were asked for:
+/// `path:line:column:offset:text`. Context lines use `-` instead of `:`.
+/// With `json` each of those is a JSON object on a line of its own instead,
+/// along with ones for the beginning and end of each file that had any.
+#[derive(Debug, Default)]
+pub struct Printer {
+    pub with_path: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub column: bool,
+    /// one `path:line:column:text` line per match, for editors' quickfix lists
+    pub vimgrep: bool,
+    /// how to color the output, if at all
+    pub colors: Option<Colors>,
+    pub json: bool,
+    /// the template matches are replaced with in matching lines
+    pub replace: Option<String>,
+    /// print each match on a line of its own, instead of the whole line
+    pub only_matching: bool,
+    /// print each match through the template, instead of the whole line
+    pub format: Option<Format>,
+    /// lines longer than this many bytes are left out, with a note instead
+    pub max_columns: Option<usize>,
+}
+
+impl Printer {
+    pub fn new(args: &Cli, with_path: bool) -> Self {
+        Printer {
+            with_path,
+            line_number: args.line_number,
+            byte_offset: args.byte_offset,
+            column: args.column,
+            vimgrep: args.vimgrep,
+            colors: None,
+            json: args.json,
+            replace: args.replace.clone(),
+            only_matching: args.only_matching,
+            format: args.format.clone(),
+            // 0 means no limit
+            max_columns: args.max_columns.filter(|&max| max > 0),
+        }
+    }
+
+    /// The style for one kind of output, which is plain without colors.
+    fn style(&self, kind: fn(&Colors) -> &Style) -> Style {
+        self.colors.as_ref().map(kind).copied().unwrap_or_default()
+    }
+
+    /// Write the line, highlighting the `matches` in it, and end it with `eol`,
+    /// the line's own terminator.
+    fn line(&self, out: &mut dyn Write, line: &[u8], eol: &[u8], matches: Option<&[(usize, usize)]>) -> io::Result<()> {
+        let style = self.style(|colors| &colors.matched);
+        let mut last = 0;
+        if let (Some(matches), true) = (matches, self.colors.is_some()) {
+            for &(start, end) in matches.iter().filter(|(start, end)| start < end) {
+                write!(out, "{}", String::from_utf8_lossy(&line[last..start]))?;
+                write!(out, "{}", style.paint(String::from_utf8_lossy(&line[start..end])))?;
+                last = end;
+            }
+        }
+        write!(out, "{}", String::from_utf8_lossy(&line[last..]))?;
+        end_line(out, eol)
+    }
+
+    fn write_path(&self, out: &mut dyn Write, path: &Path) -> io::Result<()> {
+        write!(out, "{}", self.style(|colors| &colors.path).paint(path.display()))
+    }
+
+    fn write_sep(&self, out: &mut dyn Write, sep: char) -> io::Result<()> {
+        write!(out, "{}", self.style(|colors| &colors.separator).paint(sep))
+    }
+
+    /// Print a matching line which starts `offset` bytes into the input.
+    /// The line comes with its terminator, which is printed as it is,
+    /// so CRLF input gives CRLF output. Bytes that aren't UTF-8 are printed as U+FFFD.
+    ///
+    /// `found` is where the matches are in the line, if the searcher knows
+    /// better than the matcher would on the line alone, as with `--multiline`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn matched(
+        &self,
+        out: &mut dyn Write,
+        path: &Path,
+        line_number: u64,
+        offset: u64,
+        line: &[u8],
+        matcher: &Matcher,
+        found: Option<&[(usize, usize)]>,
+    ) -> io::Result<()> {
+        let (line, eol) = split_eol(line);
+        let needed = self.json || self.only_matching || self.vimgrep || self.column || self.colors.is_some() || self.max_columns.is_some();
+        let matches: Vec<(usize, usize)> = match found {
+            Some(found) => found.to_vec(),
+            None if needed => matcher.find_iter(line).collect(),
+            None => Vec::new(),
+        };
+        let replaced = self.replace.as_ref().map(|template| matcher.replace(line, template));
+        // the matches are gone from a replaced line, so there's nothing to highlight
+        let (text, highlight) = match &replaced {
+            Some(replaced) => (&replaced[..], None),
+            None => (line, Some(&matches[..])),
+        };
+        if self.json {
+            return self.json_line(out, "match", path, line_number, offset, line, &matches);
+        }
+        if let Some(format) = &self.format {
+            return self.formatted(out, format, path, line_number, line, matcher);
+        }
+        if self.only_matching || self.vimgrep {
+            let mut spots = matches.clone();
+            if spots.is_empty() && !self.only_matching {
+                // an inverted match, which is all of the line
+                spots.push((0, line.len()));
+            }
+            for (start, end) in spots {
+                if self.vimgrep {
+                    self.write_path(out, path)?;
+                    self.write_sep(out, ':')?;
+                    write!(out, "{}", self.style(|colors| &colors.line).paint(line_number))?;
+                    self.write_sep(out, ':')?;
+                    write!(out, "{}", self.style(|colors| &colors.column).paint(start + 1))?;
+                    self.write_sep(out, ':')?;
+                    if self.byte_offset {
+                        write!(out, "{}", offset + start as u64)?;
+                        self.write_sep(out, ':')?;
+                    }
+                } else {
+                    let column = if self.column { Some(start + 1) } else { None };
+                    self.prefix(out, path, line_number, column, offset + start as u64, ':')?;
+                }
+                if !self.only_matching {
+                    self.matched_line(out, line, text, eol, highlight, matches.len())?;
+                    continue;
+                }
+                let matched = &line[start..end];
+                match &self.replace {
+                    Some(template) => writeln!(out, "{}", String::from_utf8_lossy(&matcher.replace(matched, template)))?,
+                    None => {
+                        let style = self.style(|colors| &colors.matched);
+                        writeln!(out, "{}", style.paint(String::from_utf8_lossy(matched)))?
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let column = if self.column { matches.first().map(|(start, _)| start + 1) } else { None };
+        self.prefix(out, path, line_number, column, offset, ':')?;
+        self.matched_line(out, line, text, eol, highlight, matches.len())
+    }
+
+    /// Write a matching line as `text`, unless the line is too long to show.
+    fn matched_line(
+        &self,
+        out: &mut dyn Write,
+        line: &[u8],
+        text: &[u8],
+        eol: &[u8],
+        highlight: Option<&[(usize, usize)]>,
+        matches: usize,
+    ) -> io::Result<()> {
+        if self.too_long(line) {
+            write!(out, "[omitted long line with {} matches]", matches)?;
+            return end_line(out, eol);
+        }
+        self.line(out, text, eol, highlight)
+    }
+
+    fn too_long(&self, line: &[u8]) -> bool {
+        self.max_columns.is_some_and(|max| line.len() > max)
+    }
+
+    /// Print a line with its matches put into the `--format` template.
+    fn formatted(
+        &self,
+        out: &mut dyn Write,
+        format: &Format,
+        path: &Path,
+        line_number: u64,
+        line: &[u8],
+        matcher: &Matcher,
+    ) -> io::Result<()> {
+        let mut found = false;
+        for groups in matcher.captures_iter(line) {
+            found = true;
+            format.render(out, path, line_number, line, &groups, matcher)?;
+        }
+        if !found {
+            // an inverted match: the whole line is the match
+            format.render(out, path, line_number, line, &[Some((0, line.len()))], matcher)?;
+        }
+        Ok(())
+    }
+
+    /// Print a line shown around a match.
+    pub fn context(&self, out: &mut dyn Write, path: &Path, line_number: u64, offset: u64, line: &[u8]) -> io::Result<()> {
+        let (line, eol) = split_eol(line);
+        if self.json {
+            return self.json_line(out, "context", path, line_number, offset, line, &[]);
+        }
+        // only matched text is printed, which context lines have none of
+        if self.vimgrep || self.only_matching || self.format.is_some() {
+            return Ok(());
+        }
+        self.prefix(out, path, line_number, None, offset, '-')?;
+        if self.too_long(line) {
+            write!(out, "[omitted long context line]")?;
+            return end_line(out, eol);
+        }
+        self.line(out, line, eol, None)
+    }
+
+    /// Print how many lines of a file matched.
+    pub fn count(&self, out: &mut dyn Write, path: &Path, count: u64) -> io::Result<()> {
+        if self.with_path {
+            self.write_path(out, path)?;
+            self.write_sep(out, ':')?;
+        }
+        writeln!(out, "{}", count)
+    }
+
+    /// Print the one line a binary file gets instead of its matches.
+    /// For JSON that's left to the file's `end`.
+    pub fn binary_matches(&self, out: &mut dyn Write, path: &Path) -> io::Result<()> {
+        if self.json {
+            return Ok(());
+        }
+        write!(out, "Binary file ")?;
+        self.write_path(out, path)?;
+        writeln!(out, " matches")
+    }
+
+    /// Print just the path of a file, for `-l` and `-L`.
+    pub fn path(&self, out: &mut dyn Write, path: &Path) -> io::Result<()> {
+        self.write_path(out, path)?;
+        writeln!(out)
+    }
+
+    /// Print the line between groups of context that aren't next to each other.
+    pub fn separator(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.vimgrep || self.json || self.only_matching || self.format.is_some() {
+            return Ok(());
+        }
+        writeln!(out, "{}", self.style(|colors| &colors.separator).paint("--"))
+    }
+
+    /// Print the `begin` of a file, ahead of its first line.
+    pub fn begin(&self, out: &mut dyn Write, path: &Path) -> io::Result<()> {
+        if !self.json {
+            return Ok(());
+        }
+        out.write_all(br#"{"type":"begin","data":{"path":"#)?;
+        json::write_path(out, path)?;
+        out.write_all(b"}}\n")
+    }
+
+    /// Print the `end` of a file, with how much of it matched.
+    pub fn end(&self, out: &mut dyn Write, path: &Path, matched_lines: u64, bytes_searched: u64) -> io::Result<()> {
+        if !self.json {
+            return Ok(());
+        }
+        out.write_all(br#"{"type":"end","data":{"path":"#)?;
+        json::write_path(out, path)?;
+        writeln!(
+            out,
+            r#","stats":{{"matched_lines":{},"bytes_searched":{}}}}}}}"#,
+            matched_lines, bytes_searched
+        )
+    }
+
+    /// Print the `summary` that closes JSON output.
+    pub fn summary(&self, out: &mut dyn Write, searches: u64, searches_with_match: u64, matched_lines: u64, elapsed: Duration) -> io::Result<()> {
+        if !self.json {
+            return Ok(());
+        }
+        writeln!(
+            out,
+            r#"{{"type":"summary","data":{{"elapsed_secs":{:.6},"stats":{{"searches":{},"searches_with_match":{},"matched_lines":{}}}}}}}"#,
+            elapsed.as_secs_f64(),
+            searches,
+            searches_with_match,
+            matched_lines
+        )
+    }
+
+    /// A `match` or `context` line, with the spans that matched in it.
+    #[allow(clippy::too_many_arguments)]
+    fn json_line(
+        &self,
+        out: &mut dyn Write,
+        kind: &str,
+        path: &Path,
+        line_number: u64,
+        offset: u64,
+        line: &[u8],
+        submatches: &[(usize, usize)],
+    ) -> io::Result<()> {
+        write!(out, r#"{{"type":"{}","data":{{"path":"#, kind)?;
+        json::write_path(out, path)?;
+        out.write_all(br#","lines":"#)?;
+        json::write_data(out, line)?;
+        write!(out, r#","line_number":{},"absolute_offset":{},"submatches":["#, line_number, offset)?;
+        for (i, &(start, end)) in submatches.iter().enumerate() {
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            out.write_all(br#"{"match":"#)?;
+            json::write_data(out, &line[start..end])?;
+            write!(out, r#","start":{},"end":{}}}"#, start, end)?;
+        }
+        out.write_all(b"]}}\n")
+    }
+
+    fn prefix(
+        &self,
+        out: &mut dyn Write,
+        path: &Path,
+        line_number: u64,
+        column: Option<usize>,
+        offset: u64,
+        sep: char,
+    ) -> io::Result<()> {
+        if self.with_path {
+            self.write_path(out, path)?;
+            self.write_sep(out, sep)?;
+        }
+        if self.line_number {
+            write!(out, "{}", self.style(|colors| &colors.line).paint(line_number))?;
+            self.write_sep(out, sep)?;
+        }
+        if let Some(column) = column {
+            write!(out, "{}", self.style(|colors| &colors.column).paint(column))?;
+            self.write_sep(out, sep)?;
+        }
+        if self.byte_offset {
+            write!(out, "{}", offset)?;
+            self.write_sep(out, sep)?;
+        }
+        Ok(())
+    }
+}
+
+/// Split a line into its text and its terminator: `\r\n`, `\n`, or nothing
+/// for a last line without one.
+pub(crate) fn split_eol(line: &[u8]) -> (&[u8], &[u8]) {
+    let text = line.strip_suffix(b"\n").unwrap_or(line);
+    let text = text.strip_suffix(b"\r").unwrap_or(text);
+    line.split_at(text.len())
+}
+
+/// End a printed line the way the input line ended, or with `\n` if it didn't.
+fn end_line(out: &mut dyn Write, eol: &[u8]) -> io::Result<()> {
+    out.write_all(if eol.is_empty() { b"\n" } else { eol })
+}
+
+#[cfg(test)]
+mod test {
+    use super::Printer;
+    use crate::color::Colors;
+    use crate::Matcher;
+    use std::path::Path;
+
+    #[test]
+    fn colored() {
+        let printer = Printer { with_path: true, line_number: true, colors: Some(Colors::default()), ..Printer::default() };
+        let matcher = Matcher::new("a").unwrap();
+        let mut out = Vec::new();
+        printer.matched(&mut out, Path::new("t.txt"), 2, 0, b"bar: 2a", &matcher, None).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[35mt.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m2\x1b[0m\x1b[36m:\x1b[0m\
+             b\x1b[1;31ma\x1b[0mr: 2\x1b[1;31ma\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let printer = Printer { json: true, ..Printer::default() };
+        let matcher = Matcher::new("a").unwrap();
+        let mut out = Vec::new();
+        printer.begin(&mut out, Path::new("a:b.txt")).unwrap();
+        printer.matched(&mut out, Path::new("a:b.txt"), 2, 9, b"bar: \"2a\"", &matcher, None).unwrap();
+        printer.context(&mut out, Path::new("a:b.txt"), 3, 19, b"\xFF").unwrap();
+        printer.end(&mut out, Path::new("a:b.txt"), 1, 21).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#"{"type":"begin","data":{"path":{"text":"a:b.txt"}}}
+{"type":"match","data":{"path":{"text":"a:b.txt"},"lines":{"text":"bar: \"2a\""},"line_number":2,"absolute_offset":9,"submatches":[{"match":{"text":"a"},"start":1,"end":2},{"match":{"text":"a"},"start":7,"end":8}]}}
+{"type":"context","data":{"path":{"text":"a:b.txt"},"lines":{"bytes":"/w=="},"line_number":3,"absolute_offset":19,"submatches":[]}}
+{"type":"end","data":{"path":{"text":"a:b.txt"},"stats":{"matched_lines":1,"bytes_searched":21}}}
+"#);
+    }
+
+    #[test]
+    fn only_matching() {
+        let printer = Printer { line_number: true, byte_offset: true, only_matching: true, ..Printer::default() };
+        let matcher = Matcher::new(r"\d+").unwrap();
+        let mut out = Vec::new();
+        printer.matched(&mut out, Path::new("t.txt"), 2, 8, b"bar: 20 and 300", &matcher, None).unwrap();
+        printer.context(&mut out, Path::new("t.txt"), 3, 24, b"baz").unwrap();
+        assert_eq!(out, b"2:13:20\n2:20:300\n");
+    }
+
+    #[test]
+    fn format() {
+        let format = "{path}:{line}:{column}: {1}={2}{3}{key}".parse().unwrap();
+        let printer = Printer { format: Some(format), ..Printer::default() };
+        let matcher = Matcher::new(r"(\w+): (?P<key>\d+)").unwrap();
+        let mut out = Vec::new();
+        printer.matched(&mut out, Path::new("t.txt"), 2, 8, b"bar: 20, baz: 30", &matcher, None).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "t.txt:2:1: bar=2020\nt.txt:2:10: baz=3030\n");
+    }
+
+    #[test]
+    fn max_columns() {
+        let printer = Printer { line_number: true, max_columns: Some(8), ..Printer::default() };
+        let matcher = Matcher::new("a").unwrap();
+        let mut out = Vec::new();
+        printer.matched(&mut out, Path::new("t.txt"), 2, 0, b"bar: 20", &matcher, None).unwrap();
+        printer.matched(&mut out, Path::new("t.txt"), 3, 8, b"var a=1;var b=2", &matcher, None).unwrap();
+        printer.context(&mut out, Path::new("t.txt"), 4, 24, b"const x = 1;").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2:bar: 20\n3:[omitted long line with 3 matches]\n4-[omitted long context line]\n"
+        );
+    }
+
+    #[test]
+    fn crlf() {
+        let printer = Printer { line_number: true, replace: Some("X".to_string()), ..Printer::default() };
+        let matcher = Matcher::new("a$").unwrap();
+        let mut out = Vec::new();
+        printer.matched(&mut out, Path::new("t.txt"), 1, 0, b"bara\r\n", &matcher, None).unwrap();
+        printer.context(&mut out, Path::new("t.txt"), 2, 6, b"baz\r\n").unwrap();
+        printer.matched(&mut out, Path::new("t.txt"), 3, 11, b"quxa", &matcher, None).unwrap();
+        assert_eq!(out, b"1:barX\r\n2-baz\r\n3:quxX\n");
+    }
+
+    #[test]
+    fn plain() {
+        let printer = Printer { with_path: true, line_number: true, ..Printer::default() };
+        let matcher = Matcher::new("a").unwrap();
+        let mut out = Vec::new();
+        printer.matched(&mut out, Path::new("t.txt"), 2, 0, b"bar: 2a", &matcher, None).unwrap();
+        assert_eq!(out, b"t.txt:2:bar: 2a\n");
+    }
+}
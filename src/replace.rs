@@ -0,0 +1,267 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::encoding;
+use crate::search::{Binary, BINARY_BLOCK};
+use crate::{AtomicFile, Error, Matcher, Result};
+
+/// Lines of context around each change in a diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// Rewrites files with every match replaced, for `--replace --in-place`.
+/// The new contents replace a file in one rename, with its permissions kept,
+/// so a file is either all old or all new, whatever happens in between.
+pub struct Rewriter<'a> {
+    matcher: &'a Matcher,
+    template: &'a str,
+    backup: bool,
+    dry_run: bool,
+    binary: Binary,
+}
+
+/// A line of the file, before and after, each with its line break if any.
+struct Change<'a> {
+    old: &'a [u8],
+    new: Vec<u8>,
+}
+
+impl Change<'_> {
+    fn changed(&self) -> bool {
+        self.old != &self.new[..]
+    }
+}
+
+impl<'a> Rewriter<'a> {
+    pub fn new(matcher: &'a Matcher, template: &'a str) -> Self {
+        Rewriter { matcher, template, backup: false, dry_run: false, binary: Binary::Matches }
+    }
+
+    /// Keep the original next to the file, as `name.bak`.
+    pub fn backup(mut self, yes: bool) -> Self {
+        self.backup = yes;
+        self
+    }
+
+    /// Print a unified diff of what would change instead of changing it.
+    pub fn dry_run(mut self, yes: bool) -> Self {
+        self.dry_run = yes;
+        self
+    }
+
+    /// Binary files are left alone, unless they're to be treated as text.
+    pub fn binary(mut self, binary: Binary) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    /// Rewrite the file, returning how many lines had a match.
+    pub fn rewrite(&self, path: &Path, out: &mut dyn Write) -> Result<u64> {
+        let io_error = |source| Error::Io { path: path.to_path_buf(), source };
+        if path == Path::new("-") {
+            let source = io::Error::new(io::ErrorKind::InvalidInput, "stdin can't be rewritten in place");
+            return Err(io_error(source));
+        }
+        let old = fs::read(path).map_err(io_error)?;
+        // replacements are spliced in as UTF-8, which would garble UTF-16
+        if encoding::is_utf16(&old) {
+            let source = io::Error::new(io::ErrorKind::InvalidData, "UTF-16 files can't be rewritten in place");
+            return Err(io_error(source));
+        }
+        if self.binary != Binary::Text && old[..old.len().min(BINARY_BLOCK)].contains(&0) {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        let changes: Vec<Change> = old
+            .split_inclusive(|&b| b == b'\n')
+            .map(|line| {
+                let body = line.strip_suffix(b"\n").unwrap_or(line);
+                let body = body.strip_suffix(b"\r").unwrap_or(body);
+                if !self.matcher.is_match(body) {
+                    return Change { old: line, new: line.to_vec() };
+                }
+                count += 1;
+                let mut new = self.matcher.replace(body, self.template).into_owned();
+                new.extend_from_slice(&line[body.len()..]);
+                Change { old: line, new }
+            })
+            .collect();
+        if !changes.iter().any(Change::changed) {
+            return Ok(count);
+        }
+
+        if self.dry_run {
+            diff(out, path, &changes)?;
+            return Ok(count);
+        }
+        // a symlink is followed, so the file it points to gets the changes
+        let dest = fs::canonicalize(path).map_err(io_error)?;
+        let permissions = fs::metadata(&dest).map_err(io_error)?.permissions();
+        if self.backup {
+            fs::copy(&dest, backup_path(&dest)).map_err(io_error)?;
+        }
+        let write = || -> io::Result<()> {
+            let mut file = AtomicFile::create(&dest)?;
+            for change in &changes {
+                file.write_all(&change.new)?;
+            }
+            file.set_permissions(permissions)?;
+            file.commit()
+        };
+        write().map_err(io_error)?;
+        Ok(count)
+    }
+}
+
+/// `name.bak` next to `path`.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.file_name().unwrap_or_default());
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Print the changes as a unified diff, the way `diff -u` would.
+/// Every line maps to exactly one replacement, so there's no need to look
+/// for the shortest edit: the hunks are the changed lines and their context.
+fn diff(out: &mut dyn Write, path: &Path, changes: &[Change]) -> io::Result<()> {
+    writeln!(out, "--- {}", path.display())?;
+    writeln!(out, "+++ {}", path.display())?;
+
+    let changed: Vec<usize> = (0..changes.len()).filter(|&i| changes[i].changed()).collect();
+    let mut i = 0;
+    while i < changed.len() {
+        // take in changes until the gap to the next is too big to share context
+        let mut j = i;
+        while j + 1 < changed.len() && changed[j + 1] - changed[j] <= 2 * DIFF_CONTEXT {
+            j += 1;
+        }
+        let start = changed[i].saturating_sub(DIFF_CONTEXT);
+        let end = (changed[j] + DIFF_CONTEXT + 1).min(changes.len());
+        hunk(out, changes, start, end)?;
+        i = j + 1;
+    }
+    Ok(())
+}
+
+/// Print the hunk for the lines `start..end`.
+fn hunk(out: &mut dyn Write, changes: &[Change], start: usize, end: usize) -> io::Result<()> {
+    let lines = |text: &[u8]| text.split_inclusive(|&b| b == b'\n').count();
+    let new_before: usize = changes[..start].iter().map(|change| lines(&change.new)).sum();
+    let new_len: usize = changes[start..end].iter().map(|change| lines(&change.new)).sum();
+    // an empty range is named by the line before it
+    let new_start = if new_len == 0 { new_before } else { new_before + 1 };
+    writeln!(out, "@@ -{},{} +{},{} @@", start + 1, end - start, new_start, new_len)?;
+
+    let mut k = start;
+    while k < end {
+        if !changes[k].changed() {
+            line(out, b' ', changes[k].old)?;
+            k += 1;
+            continue;
+        }
+        let run = k..(k..end).find(|&k| !changes[k].changed()).unwrap_or(end);
+        for change in &changes[run.clone()] {
+            line(out, b'-', change.old)?;
+        }
+        for change in &changes[run.clone()] {
+            for new in change.new.split_inclusive(|&b| b == b'\n') {
+                line(out, b'+', new)?;
+            }
+        }
+        k = run.end;
+    }
+    Ok(())
+}
+
+fn line(out: &mut dyn Write, mark: u8, text: &[u8]) -> io::Result<()> {
+    out.write_all(&[mark])?;
+    out.write_all(text)?;
+    if !text.ends_with(b"\n") {
+        out.write_all(b"\n\\ No newline at end of file\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Rewriter;
+    use crate::Matcher;
+    use std::fs;
+
+    #[test]
+    fn in_place() {
+        let dir = std::env::temp_dir().join(format!("grrs-replace-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        fs::write(&path, "foo: 10\r\nbar: 20\nbaz: 30").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        let matcher = Matcher::new(r"(\w+): (\d+)").unwrap();
+        let mut out = Vec::new();
+        let count = Rewriter::new(&matcher, "$2=$1").backup(true).rewrite(&path, &mut out).unwrap();
+        assert_eq!(count, 3);
+        assert!(out.is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "10=foo\r\n20=bar\n30=baz");
+        assert_eq!(fs::read_to_string(dir.join("a.txt.bak")).unwrap(), "foo: 10\r\nbar: 20\nbaz: 30");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn utf16() {
+        let path = std::env::temp_dir().join(format!("grrs-replace-utf16-{}", std::process::id()));
+        fs::write(&path, b"\xFF\xFEa\x00\n\x00").unwrap();
+        let matcher = Matcher::new("a").unwrap();
+        assert!(Rewriter::new(&matcher, "b").rewrite(&path, &mut Vec::new()).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"\xFF\xFEa\x00\n\x00");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn dry_run() {
+        let path = std::env::temp_dir().join(format!("grrs-replace-dry-{}", std::process::id()));
+        let lines: String = (1..=12).map(|n| format!("line {}\n", n)).collect();
+        fs::write(&path, &lines).unwrap();
+
+        let matcher = Matcher::new(r"^line (2|4|12)$").unwrap();
+        let mut out = Vec::new();
+        let count = Rewriter::new(&matcher, "LINE $1").dry_run(true).rewrite(&path, &mut out).unwrap();
+        assert_eq!(count, 3);
+        // nothing was written
+        assert_eq!(fs::read_to_string(&path).unwrap(), lines);
+        let name = path.display();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("\
+--- {name}
++++ {name}
+@@ -1,7 +1,7 @@
+ line 1
+-line 2
++LINE 2
+ line 3
+-line 4
++LINE 4
+ line 5
+ line 6
+ line 7
+@@ -9,4 +9,4 @@
+ line 9
+ line 10
+ line 11
+-line 12
++LINE 12
+", name = name));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
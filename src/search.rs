@@ -0,0 +1,713 @@
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use linked_lists::Deque;
+
+use crate::encoding::{self, Encoding};
+use crate::mmap::Map;
+use crate::printer::split_eol;
+use crate::{Error, Matcher, Printer, Result};
+
+/// How much of the start of a file is looked at to tell whether it's binary.
+pub(crate) const BINARY_BLOCK: usize = 8 * 1024;
+
+/// Files at least this big are memory mapped, unless told otherwise.
+const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Runs the matcher over an input one line at a time,
+/// keeping track of where each line starts, and hands matching lines
+/// and the context around them to the printer.
+pub struct Searcher<'a> {
+    matcher: &'a Matcher,
+    printer: &'a Printer,
+    before_context: usize,
+    after_context: usize,
+    invert: bool,
+    mode: Mode,
+    encoding: Encoding,
+    binary: Binary,
+    mmap: MmapChoice,
+    multiline: bool,
+    multiline_max_size: u64,
+    max_count: Option<u64>,
+}
+
+/// What gets reported for each input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// the matching lines themselves
+    Lines,
+    /// how many lines matched
+    Count,
+    /// the path, if anything matched
+    FilesWithMatches,
+    /// the path, if nothing matched
+    FilesWithoutMatch,
+}
+
+/// What to do with binary files, i.e. ones with a NUL byte near the start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binary {
+    /// say the file matches, instead of printing what are hardly lines
+    Matches,
+    /// leave the file out of the search
+    Skip,
+    /// search it like any other file
+    Text,
+}
+
+impl FromStr for Binary {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "matches" => Ok(Binary::Matches),
+            "skip" => Ok(Binary::Skip),
+            "text" => Ok(Binary::Text),
+            _ => Err(format!("unknown binary mode {:?}, expected matches, skip or text", s)),
+        }
+    }
+}
+
+/// Whether files are memory mapped or read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MmapChoice {
+    /// map big files, read the rest
+    Auto,
+    /// map whatever can be mapped
+    Always,
+    Never,
+}
+
+/// A line held back in case a later line matches and needs it as context.
+struct Line {
+    number: u64,
+    offset: u64,
+    text: Vec<u8>,
+}
+
+impl<'a> Searcher<'a> {
+    pub fn new(matcher: &'a Matcher, printer: &'a Printer) -> Self {
+        Searcher { matcher, printer, before_context: 0, after_context: 0, invert: false, mode: Mode::Lines, encoding: Encoding::Auto, binary: Binary::Matches, mmap: MmapChoice::Auto, multiline: false, multiline_max_size: u64::MAX, max_count: None }
+    }
+
+    /// Select the lines that don't match instead.
+    pub fn invert(mut self, yes: bool) -> Self {
+        self.invert = yes;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Show `before` lines ahead of every match and `after` lines behind it.
+    pub fn context(mut self, before: usize, after: usize) -> Self {
+        self.before_context = before;
+        self.after_context = after;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn binary(mut self, binary: Binary) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    pub fn mmap(mut self, mmap: MmapChoice) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Run the matcher over the whole input at once, so a match can span lines;
+    /// every line it touches is selected. That needs the whole input in
+    /// memory, so inputs bigger than `max_size` are refused.
+    pub fn multiline(mut self, yes: bool, max_size: u64) -> Self {
+        self.multiline = yes;
+        self.multiline_max_size = max_size;
+        self
+    }
+
+    /// Stop searching a file once `max` lines were selected,
+    /// after the context that goes with the last of them.
+    pub fn max_count(mut self, max: Option<u64>) -> Self {
+        self.max_count = max;
+        self
+    }
+
+    /// Search a file, returning how many lines were selected.
+    pub fn search_path(&self, path: &Path, out: &mut dyn Write) -> Result<u64> {
+        let io_error = |source| Error::Io { path: path.to_path_buf(), source };
+        let file = File::open(path).map_err(io_error)?;
+
+        let mmap = match self.mmap {
+            MmapChoice::Never => false,
+            MmapChoice::Always => true,
+            MmapChoice::Auto => file.metadata().map_err(io_error)?.len() >= MMAP_THRESHOLD,
+        };
+        // UTF-16 has to be transcoded, which means reading it
+        if mmap && !matches!(self.encoding, Encoding::Utf16Le | Encoding::Utf16Be) {
+            if let Some(map) = Map::open(&file).map_err(io_error)? {
+                let utf16 = self.encoding == Encoding::Auto && (map.starts_with(b"\xFF\xFE") || map.starts_with(b"\xFE\xFF"));
+                if !utf16 {
+                    let buf = map.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&map);
+                    let count = self.search_slice_from(path, buf, (map.len() - buf.len()) as u64, out)?;
+                    if map.truncated() {
+                        let source = io::Error::new(io::ErrorKind::UnexpectedEof, "file was truncated while being searched");
+                        return Err(io_error(source));
+                    }
+                    return Ok(count);
+                }
+            }
+        }
+
+        let (reader, start) = encoding::decode(self.encoding, file).map_err(io_error)?;
+        self.search_reader_from(path, reader, start, out)
+    }
+
+    /// Search whatever comes in on stdin, calling it `label`.
+    pub fn search_stdin(&self, label: &Path, out: &mut dyn Write) -> Result<u64> {
+        let stdin = io::stdin();
+        let (reader, start) = encoding::decode(self.encoding, stdin.lock())
+            .map_err(|source| Error::Io { path: label.to_path_buf(), source })?;
+        self.search_reader_from(label, reader, start, out)
+    }
+
+    /// Search a whole file in memory. The matcher runs over all of it,
+    /// and the lines around matches are only worked out when there is one.
+    /// Context and inverted matches need every line, so they go line by line.
+    pub fn search_slice(&self, path: &Path, buf: &[u8], out: &mut dyn Write) -> Result<u64> {
+        self.search_slice_from(path, buf, 0, out)
+    }
+
+    /// Search a buffer that starts `base` bytes into the file,
+    /// which is where its byte offsets count from.
+    fn search_slice_from(&self, path: &Path, buf: &[u8], base: u64, out: &mut dyn Write) -> Result<u64> {
+        if self.multiline {
+            return self.search_multiline(path, buf, base, out);
+        }
+        let by_line = self.invert
+            || self.before_context > 0
+            || self.after_context > 0
+            || !self.matcher.is_line_local();
+        if by_line {
+            return self.search_reader_from(path, buf, base, out);
+        }
+
+        let binary = self.is_binary(buf);
+        if binary && self.binary == Binary::Skip {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        let mut begun = false;
+        // where the next search starts, always the start of a line
+        let mut pos = 0;
+        // line numbers are counted up to here
+        let mut counted = 0;
+        let mut line_number = 1;
+
+        while pos < buf.len() && !self.is_done(count) {
+            let (start, _) = match self.matcher.find_at(buf, pos) {
+                Some(found) => found,
+                None => break,
+            };
+            let line_start = memchr::memrchr(b'\n', &buf[pos..start]).map_or(pos, |i| pos + i + 1);
+            let line_end = memchr::memchr(b'\n', &buf[start..]).map_or(buf.len(), |i| start + i);
+            pos = line_end + 1;
+
+            // matched without its line break, printed with it
+            let line = &buf[line_start..buf.len().min(line_end + 1)];
+            if !self.matcher.is_match(split_eol(line).0) {
+                continue;
+            }
+            count += 1;
+
+            if binary && self.mode == Mode::Lines {
+                self.begin(out, path, &mut begun)?;
+                self.printer.binary_matches(out, path)?;
+                break;
+            } else if self.mode != Mode::Lines {
+                if self.mode != Mode::Count {
+                    break;
+                }
+            } else {
+                line_number += memchr::memchr_iter(b'\n', &buf[counted..line_start]).count() as u64;
+                counted = line_start;
+                self.begin(out, path, &mut begun)?;
+                self.printer.matched(out, path, line_number, base + line_start as u64, line, self.matcher, None)?;
+            }
+        }
+
+        self.finish(out, path, count, base + buf.len() as u64, begun)?;
+        Ok(count)
+    }
+
+    /// Search lines of bytes, which don't have to be UTF-8.
+    pub fn search_reader<R: BufRead>(&self, path: &Path, reader: R, out: &mut dyn Write) -> Result<u64> {
+        self.search_reader_from(path, reader, 0, out)
+    }
+
+    /// Search lines read from `base` bytes into the file,
+    /// which is where their byte offsets count from.
+    fn search_reader_from<R: BufRead>(&self, path: &Path, reader: R, base: u64, out: &mut dyn Write) -> Result<u64> {
+        if self.multiline {
+            let mut buf = Vec::new();
+            // one byte over the limit is enough to know it's too big
+            reader
+                .take(self.multiline_max_size.saturating_add(1))
+                .read_to_end(&mut buf)
+                .map_err(|source| Error::Io { path: path.to_path_buf(), source })?;
+            return self.search_multiline(path, &buf, base, out);
+        }
+        self.search_lines(path, reader, base, out, |_, line, _| self.matcher.is_match(split_eol(line).0))
+    }
+
+    /// Search a whole input for matches that may span lines.
+    fn search_multiline(&self, path: &Path, buf: &[u8], base: u64, out: &mut dyn Write) -> Result<u64> {
+        if buf.len() as u64 > self.multiline_max_size {
+            let message = format!("too big for --multiline, over {} bytes", self.multiline_max_size);
+            let source = io::Error::new(io::ErrorKind::InvalidData, message);
+            return Err(Error::Io { path: path.to_path_buf(), source });
+        }
+
+        // every match, as byte ranges of the whole input, in order
+        let mut matches: Vec<(usize, usize)> = Vec::new();
+        let mut pos = 0;
+        while pos <= buf.len() {
+            let (start, end) = match self.matcher.find_at(buf, pos) {
+                Some(found) => found,
+                None => break,
+            };
+            matches.push((start, end));
+            pos = if end > start { end } else { end + 1 };
+        }
+
+        // the first match that might still reach the line being looked at
+        let mut next = 0;
+        self.search_lines(path, buf, base, out, |offset, line, found| {
+            let start = (offset - base) as usize;
+            let end = start + line.len();
+            let text_end = start + split_eol(line).0.len();
+            // a match that ends where the line starts belongs to the line
+            // before, unless it's empty
+            while matches.get(next).is_some_and(|&(s, e)| e < start || (e == start && s < e)) {
+                next += 1;
+            }
+            // a line break belongs to the line it ends, so a match ending
+            // with one doesn't reach into the next line
+            for &(s, e) in matches[next..].iter().take_while(|&&(s, _)| s < end) {
+                let s = s.max(start).min(text_end);
+                found.push((s - start, e.min(text_end).max(s) - start));
+            }
+            !found.is_empty()
+        })
+    }
+
+    /// Go through the input line by line, printing the lines `is_match`
+    /// picks out by offset and text, or the others if inverted.
+    /// `is_match` gets each line with its line break, and can fill in where
+    /// the matches are in it when the printer couldn't find them on its own.
+    /// Offsets count from `base`, how far into the file the input starts.
+    fn search_lines<R, F>(&self, path: &Path, mut reader: R, base: u64, out: &mut dyn Write, mut is_match: F) -> Result<u64>
+    where
+        R: BufRead,
+        F: FnMut(u64, &[u8], &mut Vec<(usize, usize)>) -> bool,
+    {
+        let mut buf = Vec::new();
+        let mut line_number = 0;
+        let mut offset = base;
+        let mut count = 0;
+        let mut begun = false;
+        let io_error = |source| Error::Io { path: path.to_path_buf(), source };
+
+        let binary = self.is_binary(reader.fill_buf().map_err(io_error)?);
+        if binary && self.binary == Binary::Skip {
+            return Ok(0);
+        }
+
+        // the last few lines that weren't printed, oldest first
+        let mut before: Deque<Line> = Deque::new();
+        let mut before_len = 0;
+        // context lines still owed to the last match
+        let mut after = 0;
+        // the last line printed, to tell whether a `--` has to go before the next one
+        let mut last_printed = None;
+        // where the matches are in the line, if `is_match` says
+        let mut found = Vec::new();
+
+        loop {
+            // past the last line allowed, only the context owed to it is left
+            let done = self.is_done(count);
+            if done && (after == 0 || self.mode != Mode::Lines) {
+                break;
+            }
+            buf.clear();
+            let read = reader
+                .read_until(b'\n', &mut buf)
+                .map_err(io_error)?;
+            if read == 0 {
+                break;
+            }
+            line_number += 1;
+
+            let line = &buf[..];
+            found.clear();
+            let selected = !done && is_match(offset, line, &mut found) != self.invert;
+            if selected {
+                count += 1;
+            }
+
+            if binary && self.mode == Mode::Lines {
+                if selected {
+                    self.begin(out, path, &mut begun)?;
+                    self.printer.binary_matches(out, path)?;
+                    break;
+                }
+            } else if self.mode != Mode::Lines {
+                // one match is enough to know whether the path gets listed
+                if count > 0 && self.mode != Mode::Count {
+                    break;
+                }
+            } else if selected {
+                self.begin(out, path, &mut begun)?;
+                while let Some(held) = before.pop_front() {
+                    self.separate(out, &mut last_printed, held.number)?;
+                    self.printer.context(out, path, held.number, held.offset, &held.text)?;
+                }
+                before_len = 0;
+                self.separate(out, &mut last_printed, line_number)?;
+                let found = if self.multiline { Some(&found[..]) } else { None };
+                self.printer.matched(out, path, line_number, offset, line, self.matcher, found)?;
+                after = self.after_context;
+            } else if after > 0 {
+                self.begin(out, path, &mut begun)?;
+                self.separate(out, &mut last_printed, line_number)?;
+                self.printer.context(out, path, line_number, offset, line)?;
+                after -= 1;
+            } else if self.before_context > 0 {
+                before.push_back(Line { number: line_number, offset, text: line.to_vec() });
+                before_len += 1;
+                if before_len > self.before_context {
+                    before.pop_front();
+                    before_len -= 1;
+                }
+            }
+            offset += read as u64;
+        }
+
+        self.finish(out, path, count, offset, begun)?;
+        Ok(count)
+    }
+
+    /// Whether `count` selected lines is all a file gets.
+    fn is_done(&self, count: u64) -> bool {
+        self.max_count.is_some_and(|max| count >= max)
+    }
+
+    /// Only the first block is looked at, so telling binary files apart is cheap.
+    fn is_binary(&self, head: &[u8]) -> bool {
+        self.binary != Binary::Text && head[..head.len().min(BINARY_BLOCK)].contains(&0)
+    }
+
+    /// Whatever is printed for a file starts with its `begin`,
+    /// which only JSON has.
+    fn begin(&self, out: &mut dyn Write, path: &Path, begun: &mut bool) -> Result<()> {
+        if !*begun {
+            *begun = true;
+            self.printer.begin(out, path)?;
+        }
+        Ok(())
+    }
+
+    /// Print what the mode calls for once a file is done.
+    fn finish(&self, out: &mut dyn Write, path: &Path, count: u64, bytes_searched: u64, begun: bool) -> Result<()> {
+        match self.mode {
+            Mode::Lines if begun => self.printer.end(out, path, count, bytes_searched)?,
+            Mode::Count => self.printer.count(out, path, count)?,
+            Mode::FilesWithMatches if count > 0 => self.printer.path(out, path)?,
+            Mode::FilesWithoutMatch if count == 0 => self.printer.path(out, path)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// With context turned on, groups of lines that aren't next to each other
+    /// are told apart by a `--` line.
+    fn separate(&self, out: &mut dyn Write, last_printed: &mut Option<u64>, line_number: u64) -> Result<()> {
+        let context = self.before_context > 0 || self.after_context > 0;
+        if let Some(last) = *last_printed {
+            if context && line_number > last + 1 {
+                self.printer.separator(out)?;
+            }
+        }
+        *last_printed = Some(line_number);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Binary, MmapChoice, Mode, Searcher};
+    use crate::{Matcher, Printer};
+    use std::path::Path;
+
+    fn search(printer: Printer, pattern: &str) -> String {
+        let matcher = Matcher::new(pattern).unwrap();
+        let mut out = Vec::new();
+        Searcher::new(&matcher, &printer)
+            .search_reader(Path::new("test.txt"), "foo: 10\r\nbar: 20\nbaz: 30".as_bytes(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn search_context(pattern: &str, before: usize, after: usize) -> String {
+        let matcher = Matcher::new(pattern).unwrap();
+        let printer = Printer { line_number: true, ..Printer::default() };
+        let input = (1..=10).map(|n| format!("line {}\n", n)).collect::<String>();
+        let mut out = Vec::new();
+        Searcher::new(&matcher, &printer)
+            .context(before, after)
+            .search_reader(Path::new("lines.txt"), input.as_bytes(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn positions() {
+        let printer = Printer { line_number: true, byte_offset: true, column: true, ..Printer::default() };
+        assert_eq!(search(printer, r"\d0"), "1:6:0:foo: 10\r\n2:6:9:bar: 20\n3:6:17:baz: 30\n");
+    }
+
+    #[test]
+    fn vimgrep() {
+        let printer = Printer { vimgrep: true, ..Printer::default() };
+        assert_eq!(search(printer, "a|0"), "\
+test.txt:1:7:foo: 10\r
+test.txt:2:2:bar: 20
+test.txt:2:7:bar: 20
+test.txt:3:2:baz: 30
+test.txt:3:7:baz: 30
+");
+    }
+
+    #[test]
+    fn context() {
+        assert_eq!(search_context("line [28]$", 1, 1), "\
+1-line 1
+2:line 2
+3-line 3
+--
+7-line 7
+8:line 8
+9-line 9
+");
+        assert_eq!(search_context("line 1$", 0, 2), "1:line 1\n2-line 2\n3-line 3\n");
+        assert_eq!(search_context("line 10", 2, 0), "8-line 8\n9-line 9\n10:line 10\n");
+    }
+
+    #[test]
+    fn overlapping_context() {
+        // the windows around lines 3 and 6 meet, so there's no separator between them
+        assert_eq!(search_context("line [36]$", 2, 1), "\
+1-line 1
+2-line 2
+3:line 3
+4-line 4
+5-line 5
+6:line 6
+7-line 7
+");
+        assert_eq!(search_context("line [34]$", 1, 1), "2-line 2\n3:line 3\n4:line 4\n5-line 5\n");
+    }
+
+    fn search_mode(pattern: &str, invert: bool, mode: Mode) -> (String, u64) {
+        let matcher = Matcher::new(pattern).unwrap();
+        let printer = Printer { with_path: true, ..Printer::default() };
+        let mut out = Vec::new();
+        let count = Searcher::new(&matcher, &printer)
+            .invert(invert)
+            .mode(mode)
+            .search_reader(Path::new("test.txt"), "foo: 10\nbar: 20\nbaz: 30\n".as_bytes(), &mut out)
+            .unwrap();
+        (String::from_utf8(out).unwrap(), count)
+    }
+
+    #[test]
+    fn not_utf8() {
+        let matcher = Matcher::new("caf").unwrap();
+        let printer = Printer::default();
+        let mut out = Vec::new();
+        Searcher::new(&matcher, &printer)
+            .search_reader(Path::new("latin1.log"), &b"caf\xE9 au lait\nth\xE9\n"[..], &mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "caf\u{FFFD} au lait\n");
+    }
+
+    #[test]
+    fn invert() {
+        assert_eq!(search_mode("ba", true, Mode::Lines), ("test.txt:foo: 10\n".to_string(), 1));
+        assert_eq!(search_mode("ba", true, Mode::Count), ("test.txt:1\n".to_string(), 1));
+    }
+
+    #[test]
+    fn count() {
+        assert_eq!(search_mode("ba", false, Mode::Count), ("test.txt:2\n".to_string(), 2));
+        assert_eq!(search_mode("qux", false, Mode::Count), ("test.txt:0\n".to_string(), 0));
+    }
+
+    #[test]
+    fn files() {
+        // stops at the first match
+        assert_eq!(search_mode("ba", false, Mode::FilesWithMatches), ("test.txt\n".to_string(), 1));
+        assert_eq!(search_mode("qux", false, Mode::FilesWithMatches), (String::new(), 0));
+        assert_eq!(search_mode("ba", false, Mode::FilesWithoutMatch), (String::new(), 1));
+        assert_eq!(search_mode("qux", false, Mode::FilesWithoutMatch), ("test.txt\n".to_string(), 0));
+    }
+
+    fn search_binary(binary: Binary, mode: Mode) -> (String, u64) {
+        let matcher = Matcher::new("ELF").unwrap();
+        let printer = Printer::default();
+        let mut out = Vec::new();
+        let count = Searcher::new(&matcher, &printer)
+            .binary(binary)
+            .mode(mode)
+            .search_reader(Path::new("a.out"), &b"\x7FELF\x02\x01\x00\nELF again\n"[..], &mut out)
+            .unwrap();
+        (String::from_utf8(out).unwrap(), count)
+    }
+
+    #[test]
+    fn binary() {
+        assert_eq!(search_binary(Binary::Matches, Mode::Lines), ("Binary file a.out matches\n".to_string(), 1));
+        assert_eq!(search_binary(Binary::Matches, Mode::Count), ("2\n".to_string(), 2));
+        assert_eq!(search_binary(Binary::Skip, Mode::Lines), (String::new(), 0));
+        assert_eq!(search_binary(Binary::Skip, Mode::Count), (String::new(), 0));
+        assert_eq!(search_binary(Binary::Text, Mode::Lines), ("\u{7F}ELF\u{2}\u{1}\u{0}\nELF again\n".to_string(), 2));
+    }
+
+    #[test]
+    fn slice_like_reader() {
+        let input = b"foo: 10\r\nbar: 20\n\nbar\r\nbar\nbaz: 30\nqux\xFF: 40\nbar bar: 50";
+        let printer = Printer { line_number: true, byte_offset: true, column: true, ..Printer::default() };
+        let patterns = [r"ba\w", r"\d0$", r"^$", r"0\s+b", r"\xFF", "x*", r"(?s).", r"\Abar", r"(?-m)bar$", r"(?-R)bar$"];
+        for pattern in &patterns {
+            let matcher = Matcher::new(pattern).unwrap();
+            for &mode in &[Mode::Lines, Mode::Count, Mode::FilesWithMatches] {
+                let searcher = Searcher::new(&matcher, &printer).mode(mode);
+                let (mut by_line, mut whole) = (Vec::new(), Vec::new());
+                let expected = searcher.search_reader(Path::new("a"), &input[..], &mut by_line).unwrap();
+                let count = searcher.search_slice(Path::new("a"), input, &mut whole).unwrap();
+                assert_eq!((count, whole), (expected, by_line), "{}", pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn slice_spanning_pattern() {
+        // a match that runs over many lines would be found again from each
+        // of them, so the whole-buffer search doesn't take such patterns
+        let mut input = "x 1234567890\n".repeat(50_000);
+        input.push('y');
+        let matcher = Matcher::new("x[^y]*y").unwrap();
+        assert!(!matcher.is_line_local());
+        let printer = Printer::default();
+        let count = Searcher::new(&matcher, &printer).mode(Mode::Count).search_slice(Path::new("a"), input.as_bytes(), &mut Vec::new()).unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn max_count() {
+        let input = (1..=10).map(|n| format!("line {}\n", n)).collect::<String>();
+        let matcher = Matcher::new("line [2-9]").unwrap();
+        let printer = Printer { line_number: true, ..Printer::default() };
+        let search = |searcher: Searcher, whole: bool| {
+            let mut out = Vec::new();
+            let count = if whole {
+                searcher.search_slice(Path::new("lines.txt"), input.as_bytes(), &mut out).unwrap()
+            } else {
+                searcher.search_reader(Path::new("lines.txt"), input.as_bytes(), &mut out).unwrap()
+            };
+            (String::from_utf8(out).unwrap(), count)
+        };
+        for &whole in &[false, true] {
+            let searcher = Searcher::new(&matcher, &printer).max_count(Some(2));
+            assert_eq!(search(searcher, whole), ("2:line 2\n3:line 3\n".to_string(), 2));
+            let searcher = Searcher::new(&matcher, &printer).max_count(Some(2)).mode(Mode::Count);
+            assert_eq!(search(searcher, whole), ("2\n".to_string(), 2));
+            let searcher = Searcher::new(&matcher, &printer).max_count(Some(0));
+            assert_eq!(search(searcher, whole), (String::new(), 0));
+        }
+        // the context after the last match still comes along
+        let searcher = Searcher::new(&matcher, &printer).max_count(Some(1)).context(0, 2);
+        assert_eq!(search(searcher, false), ("2:line 2\n3-line 3\n4-line 4\n".to_string(), 1));
+    }
+
+    #[test]
+    fn multiline() {
+        let input = b"impl A {\n    fn go(\n        self,\n    ) {}\n}\n";
+        let search = |pattern: &str, max_size: u64| {
+            let matcher = Matcher::new(pattern).unwrap();
+            let printer = Printer { line_number: true, ..Printer::default() };
+            let searcher = Searcher::new(&matcher, &printer).multiline(true, max_size);
+            let mut out = Vec::new();
+            let count = searcher.search_reader(Path::new("a.rs"), &input[..], &mut out)?;
+            let mut whole = Vec::new();
+            assert_eq!(searcher.search_slice(Path::new("a.rs"), input, &mut whole)?, count);
+            assert_eq!(whole, out);
+            Ok::<_, crate::Error>((String::from_utf8(out).unwrap(), count))
+        };
+        let (out, count) = search(r"fn \w+\(\n\s+self", 1024).unwrap();
+        assert_eq!((out.as_str(), count), ("2:    fn go(\n3:        self,\n", 2));
+        // the line break ending a match doesn't select the next line
+        assert_eq!(search(r"\{\n", 1024).unwrap().0, "1:impl A {\n");
+        assert_eq!(search(r"\}\n\}", 1024).unwrap().0, "4:    ) {}\n5:}\n");
+        assert!(search("self", 16).is_err());
+
+        // the printer is told where the matches are, including the parts on other lines
+        let matcher = Matcher::new(r"go\(\n\s+self").unwrap();
+        let printer = Printer { vimgrep: true, only_matching: true, ..Printer::default() };
+        let mut out = Vec::new();
+        Searcher::new(&matcher, &printer).multiline(true, 1024).search_reader(Path::new("a.rs"), &input[..], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a.rs:2:8:go(\na.rs:3:1:        self\n");
+    }
+
+    #[test]
+    fn bom_offsets() {
+        // the byte order mark isn't searched, but it still takes up bytes
+        let path = std::env::temp_dir().join(format!("grrs-search-bom-{}", std::process::id()));
+        std::fs::write(&path, b"\xEF\xBB\xBFfoo\nbar\n").unwrap();
+        let matcher = Matcher::new("bar").unwrap();
+        let printer = Printer { byte_offset: true, ..Printer::default() };
+        for &mmap in &[MmapChoice::Always, MmapChoice::Never] {
+            for &context in &[0, 1] {
+                let mut out = Vec::new();
+                Searcher::new(&matcher, &printer).mmap(mmap).context(context, 0).search_path(&path, &mut out).unwrap();
+                let expected: &[u8] = if context == 0 { b"7:bar\n" } else { b"3-foo\n7:bar\n" };
+                assert_eq!(out, expected);
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mmap_choice() {
+        let path = std::env::temp_dir().join(format!("grrs-search-mmap-{}", std::process::id()));
+        std::fs::write(&path, "foo: 10\nbar: 20\n").unwrap();
+        let matcher = Matcher::new("bar").unwrap();
+        let printer = Printer { line_number: true, ..Printer::default() };
+        for &mmap in &[MmapChoice::Always, MmapChoice::Never, MmapChoice::Auto] {
+            let mut out = Vec::new();
+            Searcher::new(&matcher, &printer).mmap(mmap).search_path(&path, &mut out).unwrap();
+            assert_eq!(out, b"2:bar: 20\n");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{self, Write};
+
+use ignore::types::TypesBuilder;
+
+/// The file types known out of the box, for `--type` and `--type-not`.
+/// `--type-add` can add globs to these or define new ones.
+const DEFAULT_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx"]),
+    ("css", &["*.css"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.jsx", "*.mjs"]),
+    ("json", &["*.json"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// A builder for file type filters that starts out with the default types.
+pub fn builder() -> TypesBuilder {
+    let mut builder = TypesBuilder::new();
+    for (name, globs) in DEFAULT_TYPES {
+        for glob in *globs {
+            builder.add(name, glob).expect("the default types are valid");
+        }
+    }
+    builder
+}
+
+/// Print every type as `name: glob, glob`, in name order.
+pub fn list(builder: &TypesBuilder, out: &mut dyn Write) -> io::Result<()> {
+    for def in builder.definitions() {
+        writeln!(out, "{}: {}", def.name(), def.globs().join(", "))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{builder, list};
+
+    #[test]
+    fn type_list() {
+        let mut types = builder();
+        types.add_def("proto:*.proto").unwrap();
+        types.add_def("rust:*.rs.in").unwrap();
+        let mut out = Vec::new();
+        list(&types, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\nproto: *.proto\n"));
+        assert!(out.contains("\nrust: *.rs, *.rs.in\n"));
+        assert!(types.add_def("proto").is_err());
+    }
+
+    #[test]
+    fn select() {
+        let mut types = builder();
+        let types = types.select("rust").negate("md").build().unwrap();
+        assert!(types.matched("src/lib.rs", false).is_whitelist());
+        assert!(types.matched("README.md", false).is_ignore());
+        assert!(types.matched("Cargo.toml", false).is_ignore());
+        assert!(builder().select("cobol").build().is_err());
+    }
+}
@@ -3,4 +3,35 @@ use std::any::type_name;
 /// Check the type of a value
 pub fn type_of<T>(_: T) -> &'static str {
   type_name::<T>()
-}
\ No newline at end of file
+}
+
+/// Parse a size in bytes, like `512`, `64K`, `10M` or `2G`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+  let (digits, shift) = match s.char_indices().last() {
+    Some((i, 'k')) | Some((i, 'K')) => (&s[..i], 10),
+    Some((i, 'm')) | Some((i, 'M')) => (&s[..i], 20),
+    Some((i, 'g')) | Some((i, 'G')) => (&s[..i], 30),
+    _ => (s, 0),
+  };
+  digits
+    .parse::<u64>()
+    .ok()
+    .and_then(|n| n.checked_mul(1 << shift))
+    .ok_or_else(|| format!("invalid size {:?}, expected something like 512, 64K, 10M or 2G", s))
+}
+
+#[cfg(test)]
+mod test {
+  use super::parse_size;
+
+  #[test]
+  fn sizes() {
+    assert_eq!(parse_size("512"), Ok(512));
+    assert_eq!(parse_size("64K"), Ok(64 * 1024));
+    assert_eq!(parse_size("10m"), Ok(10 * 1024 * 1024));
+    assert_eq!(parse_size("2G"), Ok(2 << 30));
+    assert!(parse_size("10MB").is_err());
+    assert!(parse_size("M").is_err());
+    assert!(parse_size("").is_err());
+  }
+}
@@ -1,2 +1,399 @@
-use walkdir::WalkDir;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::types::Types;
+use ignore::Match;
+use walkdir::{DirEntry, WalkDir};
+
+use crate::Error;
+
+/// Recursive directory traversal.
+/// Yields every regular file under the root, in file name order,
+/// so a search over the same tree always visits files the same way.
+/// Hidden files and whatever `.gitignore`, `.ignore` and `.git/info/exclude`
+/// rule out are left out, unless the builder says otherwise;
+/// `.git` directories go along with the ignore rules.
+pub struct Walk {
+    inner: Box<dyn Iterator<Item = walkdir::Result<DirEntry>> + Send>,
+}
+
+/// Options for a `Walk`.
+#[derive(Debug, Clone)]
+pub struct WalkBuilder {
+    root: PathBuf,
+    hidden: bool,
+    ignore: bool,
+    globs: Vec<String>,
+    types: Types,
+    max_depth: Option<usize>,
+    max_filesize: Option<u64>,
+}
+
+impl WalkBuilder {
+    pub fn new(root: &Path) -> Self {
+        WalkBuilder {
+            root: root.to_path_buf(),
+            hidden: false,
+            ignore: true,
+            globs: Vec::new(),
+            types: Types::empty(),
+            max_depth: None,
+            max_filesize: None,
+        }
+    }
+
+    /// Only walk the paths the glob matches, or with a leading `!`,
+    /// the paths it doesn't. Globs follow `.gitignore` rules and win over
+    /// ignore files. Anything the glob rules out is pruned whole, including
+    /// a directory when the glob covers everything in it, like `!target/**`.
+    pub fn glob(&mut self, glob: &str) -> &mut Self {
+        self.globs.push(glob.to_string());
+        self
+    }
+
+    /// Walk into hidden files and directories too.
+    pub fn hidden(&mut self, yes: bool) -> &mut Self {
+        self.hidden = yes;
+        self
+    }
+
+    /// Leave out what ignore files rule out.
+    pub fn ignore(&mut self, yes: bool) -> &mut Self {
+        self.ignore = yes;
+        self
+    }
+
+    /// Only walk the files of the types selected, and none of the negated ones.
+    pub fn types(&mut self, types: Types) -> &mut Self {
+        self.types = types;
+        self
+    }
+
+    /// Go no more than `depth` directories down; the root's own files are at depth 1.
+    pub fn max_depth(&mut self, depth: Option<usize>) -> &mut Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Leave out files bigger than `size` bytes.
+    pub fn max_filesize(&mut self, size: Option<u64>) -> &mut Self {
+        self.max_filesize = size;
+        self
+    }
+
+    pub fn build(&self) -> Result<Walk, Error> {
+        let root = self.root.clone();
+        let hidden = self.hidden;
+        let ignore = self.ignore;
+        let max_filesize = self.max_filesize;
+
+        // ignore files are matched against absolute paths, since the rules
+        // from directories above the root are rooted somewhere else
+        let abs_root = fs::canonicalize(&root).unwrap_or_else(|_| root.clone());
+        let globs = self.overrides(&abs_root)?;
+        let types = self.types.clone();
+        // rules from the directories above the root, up to the repository's
+        // top, followed by the ones from each directory on the way down
+        let mut stack = if ignore { Rules::above(&abs_root) } else { Vec::new() };
+        let base = stack.len();
+
+        let mut walk = WalkDir::new(&root).sort_by_file_name();
+        if let Some(depth) = self.max_depth {
+            walk = walk.max_depth(depth);
+        }
+        let inner = walk
+            .into_iter()
+            .filter_entry(move |entry| {
+                let depth = entry.depth();
+                if depth > 0 && !hidden && is_hidden(entry) {
+                    return false;
+                }
+                let is_dir = entry.file_type().is_dir();
+                if let Some(max) = max_filesize {
+                    if !is_dir && entry.metadata().is_ok_and(|metadata| metadata.len() > max) {
+                        return false;
+                    }
+                }
+                let path = match entry.path().strip_prefix(&root) {
+                    Ok(relative) => abs_root.join(relative),
+                    Err(_) => entry.path().to_path_buf(),
+                };
+                let mut picked = false;
+                if depth > 0 {
+                    match globs.matched(&path, is_dir) {
+                        Match::Ignore(_) => return false,
+                        Match::Whitelist(_) => picked = true,
+                        Match::None => {}
+                    }
+                }
+                if depth > 0 && !picked && types.matched(&path, is_dir).is_ignore() {
+                    return false;
+                }
+                if !ignore {
+                    return true;
+                }
+                // git's own bookkeeping is never part of the work tree
+                if depth > 0 && entry.file_name() == ".git" {
+                    return false;
+                }
+
+                // only the rules from this entry's own ancestors apply
+                stack.truncate(base + depth);
+                if depth > 0 && !picked {
+                    // the innermost directory with something to say decides
+                    let ignored = stack.iter().rev().find_map(|rules| rules.matched(&path, is_dir));
+                    if ignored == Some(true) {
+                        return false;
+                    }
+                }
+                if is_dir {
+                    stack.push(Rules::load(&path));
+                }
+                true
+            });
+        Ok(Walk { inner: Box::new(inner) })
+    }
+
+    fn overrides(&self, abs_root: &Path) -> Result<Override, Error> {
+        let mut builder = OverrideBuilder::new(abs_root);
+        for glob in &self.globs {
+            let mut add = |line: &str| {
+                builder
+                    .add(line)
+                    .map(drop)
+                    .map_err(|source| Error::Glob { glob: glob.clone(), source })
+            };
+            add(glob)?;
+            // `!dir/**` leaves nothing in `dir` to walk, so skip `dir` itself
+            if let Some(dir) = glob.strip_prefix('!').and_then(|glob| glob.strip_suffix("/**")) {
+                if !dir.is_empty() {
+                    add(&format!("!{}/", dir))?;
+                }
+            }
+        }
+        builder
+            .build()
+            .map_err(|source| Error::Glob { glob: self.globs.join(" "), source })
+    }
+}
+
+impl Walk {
+    pub fn new(root: &Path) -> Self {
+        WalkBuilder::new(root).build().expect("no globs to get wrong")
+    }
+}
+
+impl Iterator for Walk {
+    type Item = walkdir::Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next()? {
+                // directories, symlinks and special files aren't searched
+                Ok(entry) if !entry.file_type().is_file() => continue,
+                Ok(entry) => return Some(Ok(entry.into_path())),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+/// The ignore rules found in one directory, the ones that win first:
+/// `.ignore`, then `.gitignore`, then `.git/info/exclude`.
+struct Rules(Vec<Gitignore>);
+
+impl Rules {
+    /// Ignore files that can't be read or parsed are passed over;
+    /// a broken rule shouldn't stop a search.
+    fn load(dir: &Path) -> Self {
+        let mut rules = Vec::new();
+        for name in &[".ignore", ".gitignore"] {
+            let path = dir.join(name);
+            if path.is_file() {
+                rules.push(Gitignore::new(&path).0);
+            }
+        }
+        let exclude = dir.join(".git").join("info").join("exclude");
+        if exclude.is_file() {
+            let mut builder = GitignoreBuilder::new(dir);
+            builder.add(&exclude);
+            if let Ok(gitignore) = builder.build() {
+                rules.push(gitignore);
+            }
+        }
+        Rules(rules)
+    }
+
+    /// The rules of the directories above `root` that still apply to it,
+    /// outermost first. That's only inside a git repository, up to its top,
+    /// so none apply when `root` is the top of a repository itself, even
+    /// one inside another.
+    fn above(root: &Path) -> Vec<Self> {
+        let ancestors: Vec<&Path> = root.ancestors().collect();
+        match ancestors.iter().position(|dir| dir.join(".git").exists()) {
+            Some(top) => ancestors[1..=top].iter().rev().map(|dir| Rules::load(dir)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// `Some(true)` if the path is ignored, `Some(false)` if a `!pattern`
+    /// lets it back in, and `None` if no rule here mentions it.
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        self.0.iter().find_map(|gitignore| match gitignore.matched(path, is_dir) {
+            Match::Ignore(_) => Some(true),
+            Match::Whitelist(_) => Some(false),
+            Match::None => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Walk, WalkBuilder};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn only_files() {
+        let files: Vec<_> = Walk::new(Path::new("src"))
+            .map(|entry| entry.unwrap())
+            .collect();
+        assert!(files.contains(&Path::new("src").join("walk.rs")));
+        assert!(files.iter().all(|path| path.is_file()));
+    }
+
+    /// Lay out a tree of files under a fresh temporary directory.
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("grrs-walk-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    fn walk(root: &Path, builder: &WalkBuilder) -> Vec<String> {
+        builder
+            .build()
+            .unwrap()
+            .map(|path| path.unwrap().strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    #[test]
+    fn ignore_files() {
+        let root = tree("ignore", &[
+            (".git/info/exclude", "*.tmp\n"),
+            (".gitignore", "target/\n*.log\n!keep.log\n"),
+            ("a.rs", ""),
+            ("a.tmp", ""),
+            ("debug.log", ""),
+            ("keep.log", ""),
+            ("target/debug/a", ""),
+            ("sub/.gitignore", "*.rs\n!keep.log\n"),
+            ("sub/.ignore", "!b.rs\n"),
+            ("sub/b.rs", ""),
+            ("sub/c.rs", ""),
+            ("sub/c.txt", ""),
+            (".hidden/d.rs", ""),
+        ]);
+
+        assert_eq!(walk(&root, &WalkBuilder::new(&root)), ["a.rs", "keep.log", "sub/b.rs", "sub/c.txt"]);
+        assert_eq!(
+            walk(&root, WalkBuilder::new(&root).hidden(true)),
+            [".gitignore", ".hidden/d.rs", "a.rs", "keep.log", "sub/.gitignore", "sub/.ignore", "sub/b.rs", "sub/c.txt"]
+        );
+        assert_eq!(walk(&root, WalkBuilder::new(&root).ignore(false)).len(), 8);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rules_from_above() {
+        let root = tree("above", &[
+            (".git/HEAD", ""),
+            (".gitignore", "*.log\n"),
+            ("sub/a.log", ""),
+            ("sub/a.rs", ""),
+        ]);
+        let sub = root.join("sub");
+        assert_eq!(walk(&sub, &WalkBuilder::new(&sub)), ["a.rs"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn nested_repository() {
+        // a repository inside another, like a project under a dotfiles repo
+        let root = tree("nested", &[
+            (".git/HEAD", ""),
+            (".gitignore", "*\n"),
+            ("proj/.git/HEAD", ""),
+            ("proj/src/a.rs", ""),
+        ]);
+        let proj = root.join("proj");
+        assert_eq!(walk(&proj, &WalkBuilder::new(&proj)), ["src/a.rs"]);
+        // below its top, the inner repository's rules are the ones that apply
+        let src = proj.join("src");
+        assert_eq!(walk(&src, &WalkBuilder::new(&src)), ["a.rs"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn globs() {
+        let root = tree("globs", &[
+            ("Cargo.toml", ""),
+            ("README.md", ""),
+            ("src/lib.rs", ""),
+            ("src/bin/a.rs", ""),
+            ("src/data.json", ""),
+            ("target/debug/b.rs", ""),
+        ]);
+
+        assert_eq!(walk(&root, WalkBuilder::new(&root).glob("*.rs").glob("!target/**")), ["src/bin/a.rs", "src/lib.rs"]);
+        assert_eq!(walk(&root, WalkBuilder::new(&root).glob("*.{md,toml}")), ["Cargo.toml", "README.md"]);
+        assert_eq!(walk(&root, WalkBuilder::new(&root).glob("src/**/[a-z].rs")), ["src/bin/a.rs"]);
+        assert_eq!(walk(&root, WalkBuilder::new(&root).glob("!src")), ["Cargo.toml", "README.md", "target/debug/b.rs"]);
+        assert!(WalkBuilder::new(&root).glob("{a,b").build().is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn types() {
+        let root = tree("types", &[("a.rs", ""), ("b.md", ""), ("sub/c.rs", ""), ("sub/d.toml", "")]);
+        let rust = crate::types::builder().select("rust").build().unwrap();
+        assert_eq!(walk(&root, WalkBuilder::new(&root).types(rust)), ["a.rs", "sub/c.rs"]);
+        let not_rust = crate::types::builder().negate("rust").build().unwrap();
+        assert_eq!(walk(&root, WalkBuilder::new(&root).types(not_rust)), ["b.md", "sub/d.toml"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn limits() {
+        let root = tree("limits", &[("a.txt", "0123456789"), ("b.txt", "0"), ("sub/c.txt", ""), ("sub/deeper/d.txt", "")]);
+        assert_eq!(walk(&root, WalkBuilder::new(&root).max_depth(Some(2))), ["a.txt", "b.txt", "sub/c.txt"]);
+        assert_eq!(walk(&root, WalkBuilder::new(&root).max_depth(Some(1)).max_filesize(Some(9))), ["b.txt"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn globs_prune() {
+        // the directory itself is ruled out, so its subtree is never walked
+        let root = Path::new("/work");
+        let globs = WalkBuilder::new(root).glob("!target/**").overrides(root).unwrap();
+        assert!(globs.matched(root.join("target"), true).is_ignore());
+        assert!(globs.matched(root.join("src"), true).is_none());
+    }
+}
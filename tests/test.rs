@@ -1,4 +1,179 @@
+use std::process::{Command, Output};
+
 #[test]
 fn basic() {
     assert_eq!(2,2);
-}
\ No newline at end of file
+}
+
+/// Run the grrs binary from the crate root, where test.txt lives.
+fn grrs(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_grrs"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn exit_status() {
+    let out = grrs(&["-p", "bar", "test.txt"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "bar: 20\n");
+    assert!(out.stderr.is_empty());
+
+    let out = grrs(&["-p", "qux", "test.txt"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+
+    let out = grrs(&["-p", "bar", "no-such-file.txt"]);
+    assert_eq!(out.status.code(), Some(2));
+
+    let out = grrs(&["-p", "ba(", "test.txt"]);
+    assert_eq!(out.status.code(), Some(2));
+
+    // arguments that don't make sense are an error too, not a miss
+    for args in [&["--bogus", "-p", "bar"][..], &["--sort", "nope", "-p", "bar"], &["--format", "{x", "-p", "bar"]] {
+        let out = grrs(args);
+        assert_eq!(out.status.code(), Some(2));
+        assert!(!out.stderr.is_empty());
+    }
+    assert_eq!(grrs(&["--help"]).status.code(), Some(0));
+}
+
+#[test]
+fn files_without_match_status() {
+    assert_eq!(grrs(&["-L", "-p", "qux", "test.txt"]).status.code(), Some(0));
+    assert_eq!(grrs(&["-L", "-p", "bar", "test.txt"]).status.code(), Some(1));
+}
+
+#[test]
+fn debug() {
+    let out = grrs(&["--debug", "-p", "bar", "test.txt"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "bar: 20\n");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("grrs::Cli"));
+}
+
+#[test]
+fn error_names_the_path() {
+    let out = grrs(&["-p", "bar", "no-such-file.txt"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.starts_with("grrs: no-such-file.txt: "), "{}", stderr);
+}
+
+#[test]
+fn colors() {
+    // piped output stays plain unless colors are forced
+    assert_eq!(String::from_utf8_lossy(&grrs(&["-p", "bar", "test.txt"]).stdout), "bar: 20\n");
+    let out = grrs(&["--color", "always", "--colors", "match:fg:blue", "-p", "bar", "test.txt"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "\x1b[1;34mbar\x1b[0m: 20\n");
+    assert_eq!(grrs(&["--colors", "match:fg:mauve", "-p", "bar", "test.txt"]).status.code(), Some(2));
+}
+
+#[test]
+fn json() {
+    let out = grrs(&["--json", "-p", "bar", "test.txt"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], r#"{"type":"begin","data":{"path":{"text":"test.txt"}}}"#);
+    assert!(lines[1].starts_with(r#"{"type":"match","#));
+    assert!(lines[3].starts_with(r#"{"type":"summary","#));
+}
+
+#[test]
+fn stdin() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let search = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_grrs"))
+            .args(args)
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"foo: 10\nbar: 20\n").unwrap();
+        String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap()
+    };
+    assert_eq!(search(&["-p", "bar"]), "bar: 20\n");
+    assert_eq!(search(&["-n", "-p", "bar", "-", "test.txt"]), "<stdin>:2:bar: 20\ntest.txt:2:bar: 20\n");
+    assert_eq!(search(&["--label", "log", "-p", "bar", "-", "test.txt"]), "log:bar: 20\ntest.txt:bar: 20\n");
+}
+
+#[test]
+fn many_patterns() {
+    let out = grrs(&["-e", "foo", "-e", "baz", "test.txt"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "foo: 10\nbaz: 30\n");
+
+    let patterns = std::env::temp_dir().join(format!("grrs-patterns-{}", std::process::id()));
+    std::fs::write(&patterns, "ba.\r\nqux\n").unwrap();
+    let out = grrs(&["-F", "-f", patterns.to_str().unwrap(), "-p", "foo", "test.txt"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "foo: 10\n");
+    let out = grrs(&["-f", patterns.to_str().unwrap(), "test.txt"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "bar: 20\nbaz: 30\n");
+
+    // a pattern that isn't UTF-8 is pinned down to its line
+    std::fs::write(&patterns, b"foo\nba\xFFr\n").unwrap();
+    let out = grrs(&["-f", patterns.to_str().unwrap(), "test.txt"]);
+    assert_eq!(out.status.code(), Some(2));
+    let expected = format!("grrs: {}:2: pattern is not valid UTF-8\n", patterns.display());
+    assert_eq!(String::from_utf8_lossy(&out.stderr), expected);
+    std::fs::remove_file(&patterns).unwrap();
+
+    assert_eq!(grrs(&["-f", "no-such-patterns.txt", "test.txt"]).status.code(), Some(2));
+}
+
+#[test]
+fn replace() {
+    let out = grrs(&["-p", r"(?P<name>\w+): (\d+)", "-r", "$2=${name}", "test.txt"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "10=foo\n20=bar\n30=baz\n");
+    // rewriting needs something to rewrite with
+    assert_eq!(grrs(&["-p", "bar", "--in-place", "test.txt"]).status.code(), Some(2));
+    // nor can it skip matches or splice into another encoding
+    for extra in [&["-v"][..], &["-m", "1"], &["-E", "utf-16le"]] {
+        let args = [&["-p", "bar", "-r", "baz", "--in-place", "--dry-run"][..], extra, &["test.txt"]].concat();
+        assert_eq!(grrs(&args).status.code(), Some(2));
+    }
+}
+
+#[test]
+fn only_matching() {
+    let out = grrs(&["--only-matching", "-p", r"\d+", "test.txt"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "10\n20\n30\n");
+    // `-o` is still --output, as it always was
+    let dest = std::env::temp_dir().join(format!("grrs-only-matching-{}.txt", std::process::id()));
+    let out = grrs(&["-p", r"\d+", "test.txt", "-o", dest.to_str().unwrap()]);
+    assert!(out.stdout.is_empty());
+    assert_eq!(std::fs::read_to_string(&dest).unwrap(), "foo: 10\nbar: 20\nbaz: 30\n");
+    std::fs::remove_file(&dest).unwrap();
+    let out = grrs(&["--format", "{path}:{line}: {1}={2}{3}", "-p", r"(\w+): (\d+)", "test.txt"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "test.txt:1: foo=10\ntest.txt:2: bar=20\ntest.txt:3: baz=30\n");
+}
+
+#[test]
+fn word_and_line() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_grrs"))
+        .args(["-w", "-p", "bar"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), b"barbaz: 15\nbar: 20\n").unwrap();
+    assert_eq!(String::from_utf8_lossy(&child.wait_with_output().unwrap().stdout), "bar: 20\n");
+
+    assert_eq!(String::from_utf8_lossy(&grrs(&["-x", "-F", "-p", "bar: 20", "test.txt"]).stdout), "bar: 20\n");
+    assert_eq!(grrs(&["-x", "-p", "bar", "test.txt"]).status.code(), Some(1));
+}
+
+#[test]
+fn limits() {
+    assert_eq!(String::from_utf8_lossy(&grrs(&["-m", "1", "-p", "ba", "test.txt"]).stdout), "bar: 20\n");
+    assert_eq!(
+        String::from_utf8_lossy(&grrs(&["--max-columns", "5", "-p", "ba", "test.txt"]).stdout),
+        "[omitted long line with 1 matches]\n[omitted long line with 1 matches]\n"
+    );
+    // the crate root holds no Rust source of its own
+    assert_eq!(grrs(&["--max-depth", "1", "-l", "-p", "fn main", "."]).status.code(), Some(1));
+    assert_eq!(grrs(&["--max-filesize", "10X", "-p", "ba", "test.txt"]).status.code(), Some(2));
+}